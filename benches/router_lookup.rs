@@ -0,0 +1,78 @@
+//! Стоимость поиска маршрута под конкуренцией потоков: Router (снимок без блокировок)
+//! против прежней схемы Mutex<HashMap>.
+//!
+//! Параметры: BENCH_ROUTES (64), BENCH_ITERS (2_000_000 на поток).
+//!
+//!     cargo bench --bench router_lookup
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::net::SocketAddr;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mc_proxy::proto::{Route, Router};
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Запустить `threads` потоков по `iters` вызовов lookup(i); вернуть среднее время вызова
+fn measure<F>(threads: usize, iters: usize, lookup: Arc<F>) -> Duration
+where
+    F: Fn(usize) -> bool + Send + Sync + 'static,
+{
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let (barrier, lookup) = (barrier.clone(), lookup.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                let start = Instant::now();
+                for i in 0..iters {
+                    black_box(lookup(i.wrapping_mul(31).wrapping_add(t)));
+                }
+                start.elapsed()
+            })
+        })
+        .collect();
+    barrier.wait();
+    let total: Duration = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    total / (threads * iters) as u32
+}
+
+fn main() {
+    let route_count = env_or("BENCH_ROUTES", 64);
+    let iters = env_or("BENCH_ITERS", 2_000_000);
+
+    let names: Arc<Vec<String>> = Arc::new((0..route_count).map(|i| format!("server{}", i)).collect());
+    let routes: Vec<Route> = names.iter().enumerate()
+        .map(|(i, name)| Route::new(
+            name.clone(),
+            SocketAddr::from(([10, 0, (i / 250) as u8, (i % 250) as u8], 25565)),
+            Some(SocketAddr::from(([10, 0, (i / 250) as u8, (i % 250) as u8], 24454))),
+        ))
+        .collect();
+
+    let router = Arc::new(Router::new());
+    router.replace_routes(routes.clone());
+    let locked: Arc<Mutex<HashMap<String, Route>>> =
+        Arc::new(Mutex::new(routes.into_iter().map(|r| (r.name.clone(), r)).collect()));
+
+    println!("router_lookup: routes={} iters/thread={}", route_count, iters);
+    println!("{:>8} {:>18} {:>18}", "threads", "Router ns/op", "Mutex ns/op");
+    let max_threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1).max(4) * 2;
+    let mut threads = 1;
+    while threads <= max_threads {
+        let (r, n) = (router.clone(), names.clone());
+        let snapshot = measure(threads, iters, Arc::new(move |i: usize| r.lookup_route(&n[i % n.len()]).is_some()));
+
+        let (m, n) = (locked.clone(), names.clone());
+        let mutex = measure(threads, iters, Arc::new(move |i: usize| {
+            m.lock().unwrap().get(&n[i % n.len()]).cloned().is_some()
+        }));
+
+        println!("{:>8} {:>18} {:>18}", threads, snapshot.as_nanos(), mutex.as_nanos());
+        threads *= 2;
+    }
+}
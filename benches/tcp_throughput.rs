@@ -0,0 +1,121 @@
+//! Пропускная способность TCP сессии через прокси на loopback: обычное копирование
+//! против splice(2) (Linux).
+//!
+//! Клиент после рукопожатия шлёт поток данных, upstream их читает и отбрасывает.
+//! Параметры через переменные окружения: BENCH_SESSIONS (4), BENCH_SECS (5).
+//!
+//!     cargo bench --bench tcp_throughput
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use mc_proxy::proto::{Route, Router, TcpProxy, VarInt};
+use mc_proxy::proto::varint::write_varint_string;
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Handshake (next state = status): маршрут выбирается по имени сервера
+fn handshake(server: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    VarInt::write(0, &mut body);
+    VarInt::write(767, &mut body);
+    write_varint_string(server, &mut body);
+    body.extend_from_slice(&25565u16.to_be_bytes());
+    VarInt::write(1, &mut body);
+    let mut packet = Vec::new();
+    VarInt::write(body.len() as i32, &mut packet);
+    packet.extend_from_slice(&body);
+    packet
+}
+
+/// Прогнать `sessions` параллельных сессий через прокси; вернуть байт/с, принятых upstream
+async fn measure(splice: bool, sessions: usize, secs: u64) -> std::io::Result<f64> {
+    // upstream: читает и отбрасывает
+    let upstream = TcpListener::bind("127.0.0.1:0").await?;
+    let upstream_addr = upstream.local_addr()?;
+    let received = Arc::new(AtomicU64::new(0));
+    {
+        let received = received.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = upstream.accept().await {
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 256 * 1024];
+                    while let Ok(n) = stream.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        received.fetch_add(n as u64, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+    }
+
+    let router = Arc::new(Router::new());
+    let mut route = Route::new("bench".to_string(), upstream_addr, None);
+    route.options.splice = splice;
+    router.add_route(route);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let proxy_addr: SocketAddr = listener.local_addr()?;
+    let accept = tokio::spawn(async move {
+        while let Ok((inbound, _)) = listener.accept().await {
+            let router = router.clone();
+            tokio::spawn(async move {
+                let _ = TcpProxy::new(inbound, router).run().await;
+            });
+        }
+    });
+
+    let running = Arc::new(AtomicBool::new(true));
+    let mut clients = Vec::new();
+    for _ in 0..sessions {
+        let mut stream = TcpStream::connect(proxy_addr).await?;
+        stream.write_all(&handshake("bench.example.com")).await?;
+        let running = running.clone();
+        clients.push(tokio::spawn(async move {
+            let data = vec![0xABu8; 256 * 1024];
+            while running.load(Ordering::Relaxed) {
+                if stream.write_all(&data).await.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    // Прогрев: сессии устанавливаются на первых пакетах
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let r0 = received.load(Ordering::Relaxed);
+    let start = Instant::now();
+    tokio::time::sleep(Duration::from_secs(secs)).await;
+    let elapsed = start.elapsed().as_secs_f64();
+    let r1 = received.load(Ordering::Relaxed);
+
+    running.store(false, Ordering::Relaxed);
+    for client in clients {
+        let _ = client.await;
+    }
+    accept.abort();
+    Ok((r1 - r0) as f64 / elapsed)
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let sessions = env_or("BENCH_SESSIONS", 4);
+    let secs = env_or("BENCH_SECS", 5) as u64;
+
+    println!("tcp_throughput: sessions={} duration={}s", sessions, secs);
+    let copy = measure(false, sessions, secs).await?;
+    println!("  copy:   {:>10.1} MiB/s", copy / (1024.0 * 1024.0));
+    if cfg!(target_os = "linux") {
+        let splice = measure(true, sessions, secs).await?;
+        println!("  splice: {:>10.1} MiB/s ({:+.0}%)", splice / (1024.0 * 1024.0), (splice / copy - 1.0) * 100.0);
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Пропускная способность UDP прокси на loopback (пакетов/с).
+//!
+//! Клиенты шлют датаграммы через прокси на upstream, который отвечает эхом.
+//! Параметры через переменные окружения:
+//! BENCH_WORKERS (по умолчанию — число ядер), BENCH_CLIENTS (8), BENCH_SECS (5), BENCH_PAYLOAD (200).
+//!
+//!     cargo bench --bench udp_pps
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+use mc_proxy::proto::{Route, Router, UdpProxy};
+use mc_proxy::proto::udp_flow::UdpLimits;
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let workers = env_or("BENCH_WORKERS", std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+    let clients = env_or("BENCH_CLIENTS", 8);
+    let secs = env_or("BENCH_SECS", 5) as u64;
+    let payload = env_or("BENCH_PAYLOAD", 200);
+
+    // upstream: эхо-сервер, считает принятые пакеты
+    let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+    let upstream_addr = upstream.local_addr()?;
+    let forwarded = Arc::new(AtomicU64::new(0));
+    {
+        let upstream = upstream.clone();
+        let forwarded = forwarded.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            while let Ok((n, from)) = upstream.recv_from(&mut buf).await {
+                forwarded.fetch_add(1, Ordering::Relaxed);
+                let _ = upstream.send_to(&buf[..n], from).await;
+            }
+        });
+    }
+
+    // UDP прокси пересылает только на udp адреса маршрутов
+    let router = Arc::new(Router::new());
+    router.replace_routes(vec![Route::new("bench".to_string(), upstream_addr, Some(upstream_addr))]);
+    let _session = router.claim_udp_session(Some("127.0.0.1".parse().unwrap()), None, upstream_addr);
+
+    // Лимиты потоков сняты, чтобы мерить сам конвейер
+    let limits = UdpLimits {
+        max_flows_per_ip: usize::MAX,
+        packets_per_sec: usize::MAX / 2,
+        packets_burst: usize::MAX / 2,
+        bytes_per_sec: usize::MAX / 2,
+        burst_bytes: usize::MAX / 2,
+        reply_packets_per_sec: usize::MAX / 2,
+        reply_packets_burst: usize::MAX / 2,
+        reply_bytes_per_sec: usize::MAX / 2,
+        reply_burst_bytes: usize::MAX / 2,
+        ..UdpLimits::default()
+    };
+    let proxy = UdpProxy::bind(SocketAddr::from(([127, 0, 0, 1], 0)), workers, router, limits)?;
+    let proxy_addr = proxy.local_addr()?;
+    tokio::spawn(async move { proxy.run().await });
+
+    let running = Arc::new(AtomicBool::new(true));
+    let replies = Arc::new(AtomicU64::new(0));
+    let sent = Arc::new(AtomicU64::new(0));
+    for _ in 0..clients {
+        let sock = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        sock.connect(proxy_addr).await?;
+
+        let (tx, running_tx, sent) = (sock.clone(), running.clone(), sent.clone());
+        tokio::spawn(async move {
+            let data = vec![0xABu8; payload];
+            while running_tx.load(Ordering::Relaxed) {
+                if tx.send(&data).await.is_ok() {
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let replies = replies.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            while sock.recv(&mut buf).await.is_ok() {
+                replies.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+
+    // Прогрев: потоки создаются на первых пакетах
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let (s0, f0, r0) = (sent.load(Ordering::Relaxed), forwarded.load(Ordering::Relaxed), replies.load(Ordering::Relaxed));
+    let start = Instant::now();
+    tokio::time::sleep(Duration::from_secs(secs)).await;
+    let elapsed = start.elapsed().as_secs_f64();
+    let (s1, f1, r1) = (sent.load(Ordering::Relaxed), forwarded.load(Ordering::Relaxed), replies.load(Ordering::Relaxed));
+    running.store(false, Ordering::Relaxed);
+
+    println!("udp_pps: workers={} clients={} payload={}B duration={:.1}s", workers, clients, payload, elapsed);
+    println!("  sent by clients:      {:>12.0} pkt/s", (s1 - s0) as f64 / elapsed);
+    println!("  client -> upstream:   {:>12.0} pkt/s", (f1 - f0) as f64 / elapsed);
+    println!("  upstream -> client:   {:>12.0} pkt/s", (r1 - r0) as f64 / elapsed);
+    Ok(())
+}
@@ -6,6 +6,9 @@ BIN_SRC="./mc-proxy"
 BIN_DST="/usr/local/bin/mc-proxy"
 SYMLINK="/usr/bin/mc-proxy"
 SERVICE_FILE="/etc/systemd/system/mc-proxy.service"
+SOCKET_FILE="/etc/systemd/system/mc-proxy.socket"
+# SOCKET_ACTIVATION=yes: порты открывает systemd (mc-proxy.socket) и передаёт их прокси
+SOCKET_ACTIVATION="${SOCKET_ACTIVATION:-no}"
 CONFIG_DIR="/etc/mc-proxy"
 CONFIG_FILE="$CONFIG_DIR/proxy.json"
 
@@ -28,8 +31,19 @@ After=network.target
 Wants=network-online.target
     
 [Service]
-Type=simple
-ExecStart=/usr/local/bin/mc-proxy
+# Прокси сообщает о готовности после загрузки маршрутов и открытия портов
+Type=notify
+# Уведомления принимаются и от нового процесса при обновлении бинаря
+NotifyAccess=all
+# Прокси подтверждает работоспособность каждые WatchdogSec/2 (не реже 10 с)
+WatchdogSec=30
+ExecStart=/usr/local/bin/mc-proxy run --config /etc/mc-proxy/proxy.json
+# Перезагрузка конфига без разрыва соединений (systemctl reload mc-proxy)
+ExecReload=/bin/kill -HUP $MAINPID
+# Обновление бинаря без простоя: установить новый файл и выполнить
+#   systemctl kill -s USR2 --kill-whom=main mc-proxy
+# SIGTERM: прокси дожидается завершения сессий (shutdown.drain_timeout_secs, по умолчанию 30 с)
+TimeoutStopSec=45
 # Перезапускать только при ошибке запуска/выполнения, но не бесконечно
 Restart=on-failure
 RestartSec=5
@@ -39,6 +53,10 @@ StartLimitIntervalSec=60
 # Запуск от непривилегированного пользователя
 User=nobody
 Group=nogroup
+# CAP_NET_ADMIN нужен для options.transparent (IP_TRANSPARENT) и options.socket.mark (SO_MARK);
+# без них строки ниже можно удалить
+AmbientCapabilities=CAP_NET_ADMIN
+CapabilityBoundingSet=CAP_NET_ADMIN
 # Безопасные опции (по желанию)
 ProtectSystem=full
 NoNewPrivileges=yes
@@ -47,29 +65,51 @@ NoNewPrivileges=yes
 WantedBy=multi-user.target
 EOF
 
+# Адреса должны совпадать с listeners в конфиге
+if [ "$SOCKET_ACTIVATION" = "yes" ]; then
+    cat > "$SOCKET_FILE" <<'EOF'
+[Unit]
+Description=MC Proxy Sockets
+
+[Socket]
+ListenStream=0.0.0.0:25526
+ListenDatagram=0.0.0.0:24454
+
+[Install]
+WantedBy=sockets.target
+EOF
+fi
+
 # Перезагрузить конфигурацию systemd
 systemctl daemon-reload
 
 # Включить автозапуск (не обязательно стартовать)
+if [ "$SOCKET_ACTIVATION" = "yes" ]; then
+    systemctl enable mc-proxy.socket
+fi
 systemctl enable mc-proxy.service
 
 # 4. Создать папку и конфиг (если ещё нет)
 mkdir -p "$CONFIG_DIR"
 cat > "$CONFIG_FILE" << 'EOF'
 {
-    "tcp_port": 25526,
-    "udp_port": 24454,
-    "endpoints": {
-        "185.24.55.33": {
-            "fractal": [54636, 24454]
-        }
-    }
+    "version": 2,
+    "listeners": {
+        "tcp": "0.0.0.0:25526",
+        "udp": "0.0.0.0:24454"
+    },
+    "routes": [
+        { "name": "fractal", "upstream": "185.24.55.33", "tcp_port": 54636, "udp_port": 24454 }
+    ]
 }
 EOF
 
 # 5. Попытаться запустить сервис и проверить состояние
 echo "Запускаю mc-proxy.service..."
 # Не прерываем выполнение скрипта при неудаче — будем проверять состояние вручную
+if [ "$SOCKET_ACTIVATION" = "yes" ] && ! systemctl start mc-proxy.socket; then
+    echo "systemctl start mc-proxy.socket вернул ошибку"
+fi
 if ! systemctl start mc-proxy.service; then
     echo "systemctl start вернул ошибку"
 fi
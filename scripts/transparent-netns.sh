@@ -0,0 +1,80 @@
+#!/bin/sh
+set -eu
+
+# Стенд для прозрачного режима (options.transparent) на одной машине:
+#
+#   mcp-client 10.77.1.2 ── 10.77.1.1 mcp-proxy 10.77.2.1 ── 10.77.2.2 mcp-backend
+#
+# Backend отвечает клиентам через прокси (маршрут по умолчанию), а прокси доставляет
+# пришедшее от backend локально — в сокеты, открытые с адресов клиентов.
+# В бою вместо правила "iif" обычно помечают только пакеты прозрачных сокетов:
+#   iptables -t mangle -A PREROUTING -m socket --transparent -j MARK --set-mark 1
+#   ip rule add fwmark 1 lookup 100
+#   ip route add local 0.0.0.0/0 dev lo table 100
+#
+#   sudo ./scripts/transparent-netns.sh up
+#   sudo ip netns exec mcp-backend <сервер на 10.77.2.2:25565 и 24454/udp>
+#   sudo ip netns exec mcp-proxy mc-proxy run --config <конфиг, см. ниже>
+#   sudo ip netns exec mcp-client <клиент на a.10.77.1.1:25565>
+#   sudo ./scripts/transparent-netns.sh down
+#
+# Конфиг для стенда (TOML):
+#   version = 2
+#   [listeners]
+#   tcp = "10.77.1.1:25565"
+#   udp = "10.77.1.1:24454"
+#   [[routes]]
+#   name = "a"
+#   upstream = "10.77.2.2"
+#   tcp_port = 25565
+#   udp_port = 24454
+#   options = { transparent = true }
+#
+# Backend должен видеть подключения с 10.77.1.2, а не с 10.77.2.1.
+
+PREFIX="${PREFIX:-mcp}"
+TABLE=100
+
+up() {
+    for ns in client proxy backend; do
+        ip netns add "$PREFIX-$ns"
+        ip -n "$PREFIX-$ns" link set lo up
+    done
+
+    ip link add "$PREFIX-c0" netns "$PREFIX-client" type veth peer name "$PREFIX-c1" netns "$PREFIX-proxy"
+    ip link add "$PREFIX-b0" netns "$PREFIX-backend" type veth peer name "$PREFIX-b1" netns "$PREFIX-proxy"
+
+    ip -n "$PREFIX-client" addr add 10.77.1.2/24 dev "$PREFIX-c0"
+    ip -n "$PREFIX-client" link set "$PREFIX-c0" up
+    ip -n "$PREFIX-client" route add default via 10.77.1.1
+
+    ip -n "$PREFIX-backend" addr add 10.77.2.2/24 dev "$PREFIX-b0"
+    ip -n "$PREFIX-backend" link set "$PREFIX-b0" up
+    ip -n "$PREFIX-backend" route add default via 10.77.2.1
+
+    ip -n "$PREFIX-proxy" addr add 10.77.1.1/24 dev "$PREFIX-c1"
+    ip -n "$PREFIX-proxy" addr add 10.77.2.1/24 dev "$PREFIX-b1"
+    ip -n "$PREFIX-proxy" link set "$PREFIX-c1" up
+    ip -n "$PREFIX-proxy" link set "$PREFIX-b1" up
+    # Всё, что пришло от backend, доставляется локально, даже на адреса клиентов
+    ip -n "$PREFIX-proxy" rule add iif "$PREFIX-b1" lookup "$TABLE"
+    ip -n "$PREFIX-proxy" route add local 0.0.0.0/0 dev lo table "$TABLE"
+
+    echo "Стенд готов: $PREFIX-client (10.77.1.2), $PREFIX-proxy (10.77.1.1, 10.77.2.1), $PREFIX-backend (10.77.2.2)"
+}
+
+down() {
+    for ns in client proxy backend; do
+        ip netns del "$PREFIX-$ns" 2>/dev/null || true
+    done
+    echo "Стенд удалён"
+}
+
+case "${1:-}" in
+    up) up ;;
+    down) down ;;
+    *)
+        echo "Использование: $0 up|down"
+        exit 1
+        ;;
+esac
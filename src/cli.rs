@@ -0,0 +1,145 @@
+use mc_proxy::configure::{ConfigFormat, LoadMode};
+
+pub const DEFAULT_CONFIG_PATH: &str = "./proxy.json";
+
+pub const USAGE: &str = "\
+Использование: mc-proxy [команда] [-c|--config <путь>]
+
+Команды:
+  run                     запустить прокси (по умолчанию)
+  check                   проверить конфиг; код выхода 1, если есть проблемы
+  print-config [--format json|toml|yaml]
+                          вывести итоговый конфиг (после миграции и подстановки значений по умолчанию)
+
+Параметры:
+  -c, --config <путь>     файл конфига (.json, .toml, .yaml), по умолчанию ./proxy.json
+  --mode strict|lenient   обработка некорректных маршрутов при запуске (по умолчанию lenient:
+                          пропустить с предупреждением; strict: не запускаться)
+  --reload-mode strict|lenient
+                          то же при перезагрузке по SIGHUP (по умолчанию strict:
+                          при ошибке остаётся прежний конфиг)
+  -h, --help              показать эту справку
+
+Сигналы (run):
+  SIGHUP                  перечитать маршруты из конфига
+  SIGUSR2                 обновление без простоя: запустить бинарь заново и передать ему
+                          слушающие сокеты; текущий процесс дождётся своих сессий и завершится
+  SIGTERM, SIGINT         остановка с ожиданием активных сессий";
+
+pub enum Command {
+    Run,
+    Check,
+    /// None — формат исходного файла
+    PrintConfig(Option<ConfigFormat>),
+    Help,
+}
+
+pub struct Cli {
+    pub command: Command,
+    pub config: String,
+    pub mode: LoadMode,
+    pub reload_mode: LoadMode,
+}
+
+impl Cli {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut command: Option<String> = None;
+        let mut config: Option<String> = None;
+        let mut format: Option<ConfigFormat> = None;
+        let mut mode: Option<LoadMode> = None;
+        let mut reload_mode: Option<LoadMode> = None;
+        let mut help = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // --flag=value и --flag value
+            let (flag, inline) = match arg.split_once('=') {
+                Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| -> Result<String, String> {
+                inline.clone().or_else(|| args.next()).ok_or_else(|| format!("{}: не указано значение", name))
+            };
+            match flag.as_str() {
+                "-h" | "--help" => help = true,
+                "-c" | "--config" => config = Some(value(&flag)?),
+                "--format" => format = Some(value(&flag)?.parse()?),
+                "--mode" => mode = Some(value(&flag)?.parse()?),
+                "--reload-mode" => reload_mode = Some(value(&flag)?.parse()?),
+                "run" | "check" | "print-config" if command.is_none() => command = Some(flag),
+                _ => return Err(format!("неизвестный аргумент '{}'", arg)),
+            }
+        }
+
+        if format.is_some() && command.as_deref() != Some("print-config") {
+            return Err("--format используется только с print-config".to_string());
+        }
+        if (mode.is_some() || reload_mode.is_some()) && command.as_deref().is_some_and(|c| c != "run") {
+            return Err("--mode и --reload-mode используются только с run".to_string());
+        }
+        let command = match (help, command.as_deref()) {
+            (true, _) => Command::Help,
+            (false, Some("check")) => Command::Check,
+            (false, Some("print-config")) => Command::PrintConfig(format),
+            _ => Command::Run,
+        };
+        Ok(Self {
+            command,
+            config: config.unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string()),
+            mode: mode.unwrap_or(LoadMode::Lenient),
+            reload_mode: reload_mode.unwrap_or(LoadMode::Strict),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        Cli::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn defaults_without_arguments() {
+        let cli = parse(&[]).unwrap();
+        assert!(matches!(cli.command, Command::Run));
+        assert_eq!(cli.config, DEFAULT_CONFIG_PATH);
+        assert_eq!(cli.mode, LoadMode::Lenient);
+        assert_eq!(cli.reload_mode, LoadMode::Strict);
+    }
+
+    #[test]
+    fn commands_and_flags() {
+        let cli = parse(&["run", "-c", "/etc/mc-proxy/proxy.toml", "--mode", "strict", "--reload-mode=lenient"]).unwrap();
+        assert!(matches!(cli.command, Command::Run));
+        assert_eq!(cli.config, "/etc/mc-proxy/proxy.toml");
+        assert_eq!((cli.mode, cli.reload_mode), (LoadMode::Strict, LoadMode::Lenient));
+
+        let cli = parse(&["--config=proxy.yaml", "check"]).unwrap();
+        assert!(matches!(cli.command, Command::Check));
+        assert_eq!(cli.config, "proxy.yaml");
+
+        assert!(matches!(parse(&["print-config"]).unwrap().command, Command::PrintConfig(None)));
+        let cli = parse(&["print-config", "--format", "toml"]).unwrap();
+        assert!(matches!(cli.command, Command::PrintConfig(Some(ConfigFormat::Toml))));
+    }
+
+    #[test]
+    fn help_wins_over_other_arguments() {
+        assert!(matches!(parse(&["-h"]).unwrap().command, Command::Help));
+        assert!(matches!(parse(&["check", "--help"]).unwrap().command, Command::Help));
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let err = |args: &[&str]| parse(args).err().unwrap();
+        assert!(err(&["--verbose"]).contains("неизвестный аргумент '--verbose'"));
+        assert!(err(&["run", "check"]).contains("неизвестный аргумент 'check'"));
+        assert!(err(&["-c"]).contains("не указано значение"));
+        assert!(err(&["--mode", "loose"]).contains("неизвестный режим"));
+        assert!(err(&["print-config", "--format", "ini"]).contains("неизвестный формат"));
+        assert!(err(&["check", "--format", "json"]).contains("только с print-config"));
+        assert!(err(&["check", "--mode", "strict"]).contains("только с run"));
+    }
+}
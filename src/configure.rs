@@ -2,89 +2,1138 @@ use std::{
     collections::{HashMap, HashSet},
     fs,
     net::{IpAddr, SocketAddr},
+    path::Path,
+    str::FromStr,
+    time::Duration,
 };
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::Router;
+use crate::proto::{Frontend, FrontendConfig, OverflowPolicy, SocketOptions};
+use crate::proto::route::{AccessList, HandshakeRewrite, IpForwarding, IpNet, ProxyProtocol, RateLimit, RouteOptions, Timeouts};
+use crate::consts::{MAX_STRING_LEN, SHUTDOWN_DRAIN_TIMEOUT};
+
+/// Префикс SRV записи Minecraft: такой узел разрешается как SRV, а не как имя хоста
+pub const SRV_PREFIX: &str = "_minecraft._tcp.";
+
+/// Upstream узел маршрута
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Upstream {
+    Ip(IpAddr),
+    /// Имя хоста, разрешается в A/AAAA и переразрешается по TTL
+    Host(String),
+    /// SRV запись `_minecraft._tcp.<домен>`: цель и порт TCP берутся из записи
+    /// (без записей — сам домен с портом из конфига), UDP порт — из конфига
+    Srv(String),
+}
+
+impl std::fmt::Display for Upstream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Upstream::Ip(ip) => write!(f, "{}", ip),
+            Upstream::Host(host) | Upstream::Srv(host) => write!(f, "{}", host),
+        }
+    }
+}
+
+/// Маршрут из конфига до разрешения имени upstream
+#[derive(Clone, Debug)]
+pub struct RouteSpec {
+    pub name: String,
+    pub upstream: Upstream,
+    pub tcp_port: u16,
+    /// None — маршрут без UDP (голосовой чат не проксируется)
+    pub udp_port: Option<u16>,
+    pub options: RouteOptions,
+}
+
+pub struct LoadedConfig {
+    pub listeners: Listeners,
+    pub shutdown: ShutdownConfig,
+    pub limits: SessionLimits,
+    pub routes: Vec<RouteSpec>,
+    /// Пропущенные записи конфига и причины
+    pub problems: Vec<String>,
+}
+
+impl LoadedConfig {
+    /// Сводка загрузки: сколько маршрутов принято и сколько записей отклонено
+    pub fn summary(&self) -> String {
+        format!("Маршрутов принято: {}, отклонено: {}", self.routes.len(), self.problems.len())
+    }
+}
+
+/// Как обращаться с некорректными записями конфига
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Любая некорректная запись отменяет загрузку (прежний конфиг остаётся в силе)
+    Strict,
+    /// Некорректные записи пропускаются с предупреждением
+    Lenient,
+}
+
+impl FromStr for LoadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "lenient" => Ok(Self::Lenient),
+            _ => Err(format!("неизвестный режим '{}' (strict или lenient)", s)),
+        }
+    }
+}
+
+/// Текущая версия схемы конфига
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Порт UDP устаревшего формата, когда `udp_port` не задан
+const LEGACY_UDP_PORT: u16 = 24454;
+
+/// Значение `rewrite_handshake`: адрес и порт самого upstream
+const HANDSHAKE_TARGET_UPSTREAM: &str = "upstream";
+
+/// Конфиг версии 2 (JSON, TOML или YAML — по расширению файла):
+///
+/// ```toml
+/// version = 2
+///
+/// [listeners]
+/// tcp = "0.0.0.0:25565"
+/// udp = "0.0.0.0:24454"   # без udp — UDP прокси не запускается
+/// socket = { keepalive_secs = 60, dscp = 46 }  # клиентские подключения
+/// # frontend = { scheme = "tcpshield", algorithm = "ecdsa-p256-sha256", public_key = "MFkw..." }
+///
+/// [shutdown]
+/// drain_timeout_secs = 30
+/// disconnect_message = "Сервер перезапускается"
+///
+/// [limits]
+/// max_sessions = 2000    # без значения — не ограничено
+/// when_full = "reject"   # или "pause"
+///
+/// [defaults.options]
+/// timeouts = { connect_secs = 5 }
+/// splice = true              # Linux: пересылка TCP без копирования в userspace
+/// socket = { bind = "10.0.0.2", mark = 100, user_timeout_ms = 30000 }  # подключения к upstream
+/// # rewrite_handshake = "upstream"  # или { address = "play.local", port = 25565 }
+/// # forwarding = "bungeecord" # backend с bungeecord: true получает IP клиента и UUID
+/// # transparent = true       # Linux: к upstream с адреса клиента (scripts/transparent-netns.sh)
+///
+/// [[routes]]
+/// name = "fractal"
+/// upstream = "185.24.55.33"  # IP, имя хоста или _minecraft._tcp.<домен>
+/// tcp_port = 54636
+/// udp_port = 24454           # необязательно
+/// ```
+///
+/// В строковых значениях подставляются переменные окружения: `${NAME}` или
+/// `${NAME:-по умолчанию}` (`$${` — литерал `${`). Значение целиком из одной подстановки,
+/// например `tcp_port = "${BACKEND_PORT}"`, становится числом или bool, если это число или bool.
+/// `include = ["conf.d"]` добавляет маршруты из файлов-фрагментов `{ routes = [...] }` в каталогах.
+/// Порты listeners переопределяются переменными `MC_PROXY_TCP_PORT` и `MC_PROXY_UDP_PORT`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document {
+    /// Проверяется до разбора документа (см. `read_document`)
+    #[serde(rename = "version")]
+    _version: u32,
+    listeners: Listeners,
+    #[serde(default)]
+    shutdown: ShutdownConfig,
+    #[serde(default)]
+    limits: SessionLimits,
+    #[serde(default)]
+    defaults: Defaults,
+    /// Маршруты разбираются по одному, чтобы ошибка в одном не отменяла остальные
+    #[serde(default)]
+    routes: Vec<Value>,
+    /// Каталоги с фрагментами маршрутов (conf.d), пути относительно файла конфига
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+/// Файл-фрагмент из каталога `include`: только дополнительные маршруты
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fragment {
+    #[serde(default)]
+    routes: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Listeners {
+    pub tcp: SocketAddr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udp: Option<SocketAddr>,
+    /// Параметры сокетов клиентских подключений
+    #[serde(default, skip_serializing_if = "SocketOptions::is_default")]
+    pub socket: SocketOptions,
+    /// Подключения приходят через фронтовой сервис (TCPShield), передающий адрес игрока
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontend: Option<FrontendConfig>,
+}
+
+/// Поведение при остановке (SIGTERM/SIGINT)
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Сколько ждать завершения активных сессий, прежде чем закрыть их принудительно
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+    /// Если задано, новые подключения во время остановки получают это сообщение
+    /// (отключение при входе, MOTD на запрос статуса), иначе приём сразу прекращается
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disconnect_message: Option<String>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self { drain_timeout_secs: default_drain_timeout_secs(), disconnect_message: None }
+    }
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    SHUTDOWN_DRAIN_TIMEOUT.as_secs()
+}
+
+/// Общий лимит одновременных TCP сессий
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionLimits {
+    /// None — не ограничено (остаётся лимит дескрипторов процесса)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sessions: Option<usize>,
+    /// Подключения сверх лимита закрываются сразу (reject) или ждут в очереди ядра (pause)
+    #[serde(default)]
+    pub when_full: OverflowPolicy,
+}
+
+/// Значения по умолчанию для всех маршрутов
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Defaults {
+    #[serde(default)]
+    options: RawOptions,
+}
 
 #[derive(Deserialize)]
-struct Config {
+#[serde(deny_unknown_fields)]
+struct RawRoute {
+    name: String,
+    upstream: String,
     tcp_port: u16,
+    udp_port: Option<u16>,
+    #[serde(default)]
+    options: RawOptions,
+}
+
+/// Устаревший формат: { "tcp_port": ..., "udp_port": ..., "endpoints": { узел: { поддомен: [tcp, udp] } } }
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LegacyConfig {
+    tcp_port: u16,
+    udp_port: Option<u16>,
     endpoints: HashMap<String, HashMap<String, (u16, u16)>>,
 }
 
-fn valid_ip(ip_str: &str) -> Option<IpAddr> {
+/// Параметры маршрута. Каждое незаданное поле берётся из `defaults.options`.
+#[derive(Clone, Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RawOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate_limit: Option<RawRateLimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeouts: Option<RawTimeouts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxy_protocol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    motd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access: Option<RawAccess>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    socket: Option<SocketOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewrite_handshake: Option<RawHandshakeRewrite>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forwarding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transparent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    splice: Option<bool>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawRateLimit {
+    bytes_per_sec: usize,
+    /// По умолчанию — два значения bytes_per_sec
+    #[serde(skip_serializing_if = "Option::is_none")]
+    burst_bytes: Option<usize>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RawTimeouts {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_secs: Option<u64>,
+}
+
+/// `"upstream"` или `{ address = "...", port = ... }`
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawHandshakeRewrite {
+    Target(String),
+    Fields(RawHandshakeFields),
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawHandshakeFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RawAccess {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    allow: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deny: Vec<String>,
+}
+
+impl RawOptions {
+    /// Параметры маршрута поверх значений по умолчанию
+    fn merged(self, defaults: &RawOptions) -> RawOptions {
+        let timeouts = match (self.timeouts, &defaults.timeouts) {
+            (Some(t), Some(d)) => Some(RawTimeouts {
+                connect_secs: t.connect_secs.or(d.connect_secs),
+                idle_secs: t.idle_secs.or(d.idle_secs),
+            }),
+            (t, d) => t.or_else(|| d.clone()),
+        };
+        RawOptions {
+            rate_limit: self.rate_limit.or_else(|| defaults.rate_limit.clone()),
+            timeouts,
+            proxy_protocol: self.proxy_protocol.or_else(|| defaults.proxy_protocol.clone()),
+            motd: self.motd.or_else(|| defaults.motd.clone()),
+            access: self.access.or_else(|| defaults.access.clone()),
+            socket: match (self.socket, &defaults.socket) {
+                (Some(s), Some(d)) => Some(s.or(d)),
+                (s, d) => s.or_else(|| d.clone()),
+            },
+            rewrite_handshake: self.rewrite_handshake.or_else(|| defaults.rewrite_handshake.clone()),
+            forwarding: self.forwarding.or_else(|| defaults.forwarding.clone()),
+            transparent: self.transparent.or(defaults.transparent),
+            splice: self.splice.or(defaults.splice),
+        }
+    }
+}
+
+impl From<&RouteOptions> for RawOptions {
+    fn from(options: &RouteOptions) -> Self {
+        let t = &options.timeouts;
+        let a = &options.access;
+        RawOptions {
+            rate_limit: options.rate_limit.map(|rl| RawRateLimit { bytes_per_sec: rl.bytes_per_sec, burst_bytes: Some(rl.burst_bytes) }),
+            timeouts: (t.connect.is_some() || t.idle.is_some()).then(|| RawTimeouts {
+                connect_secs: t.connect.map(|d| d.as_secs()),
+                idle_secs: t.idle.map(|d| d.as_secs()),
+            }),
+            proxy_protocol: options.proxy_protocol.map(|p| p.to_string()),
+            motd: options.motd.clone(),
+            access: (!a.allow.is_empty() || !a.deny.is_empty()).then(|| RawAccess {
+                allow: a.allow.iter().map(|n| n.to_string()).collect(),
+                deny: a.deny.iter().map(|n| n.to_string()).collect(),
+            }),
+            socket: (!options.socket.is_default()).then(|| options.socket.clone()),
+            rewrite_handshake: options.rewrite_handshake.as_ref().map(|r| match r {
+                HandshakeRewrite::Upstream => RawHandshakeRewrite::Target(HANDSHAKE_TARGET_UPSTREAM.to_string()),
+                HandshakeRewrite::Fixed { address, port } => RawHandshakeRewrite::Fields(RawHandshakeFields { address: address.clone(), port: *port }),
+            }),
+            forwarding: options.forwarding.map(|f| f.to_string()),
+            transparent: options.transparent.then_some(true),
+            splice: options.splice.then_some(true),
+        }
+    }
+}
+
+pub(crate) fn valid_ip(ip_str: &str) -> Option<IpAddr> {
     match ip_str.parse::<IpAddr>() {
         Ok(ip) => {
             if ip.is_unspecified() { return None; }
             if ip.is_multicast() { return None; }
-            if let IpAddr::V4(v4) = ip {
-                if v4.octets() == [255,255,255,255] { return None; }
-            }
+            if let IpAddr::V4(v4) = ip && v4.octets() == [255,255,255,255] { return None; }
             Some(ip)
         }
         Err(_) => None,
     }
 }
 
-pub fn load_and_sync(router: &Router, path: &str) -> Result<u16, String> {
+/// Разобрать узел upstream: IP, имя хоста или SRV запись
+fn parse_upstream(host: &str, hostname_re: &Regex) -> Result<Upstream, String> {
+    if host.parse::<IpAddr>().is_ok() {
+        return valid_ip(host).map(Upstream::Ip).ok_or_else(|| "некорректный IP".to_string());
+    }
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let (name, srv) = match host.strip_prefix(SRV_PREFIX) {
+        Some(domain) => (domain, true),
+        None => (host.as_str(), false),
+    };
+    if !hostname_re.is_match(name) {
+        return Err("некорректный IP или имя хоста".to_string());
+    }
+    Ok(if srv { Upstream::Srv(host) } else { Upstream::Host(host) })
+}
+
+fn parse_options(raw: RawOptions) -> Result<RouteOptions, String> {
+    let rate_limit = match raw.rate_limit {
+        Some(rl) => {
+            let burst_bytes = rl.burst_bytes.unwrap_or(rl.bytes_per_sec.saturating_mul(2));
+            if rl.bytes_per_sec == 0 || burst_bytes == 0 {
+                return Err("rate_limit: bytes_per_sec и burst_bytes должны быть больше 0".to_string());
+            }
+            Some(RateLimit { bytes_per_sec: rl.bytes_per_sec, burst_bytes })
+        }
+        None => None,
+    };
+
+    let secs = |name: &str, v: Option<u64>| match v {
+        Some(0) => Err(format!("timeouts.{}: значение должно быть больше 0", name)),
+        v => Ok(v.map(Duration::from_secs)),
+    };
+    let raw_timeouts = raw.timeouts.unwrap_or_default();
+    let timeouts = Timeouts {
+        connect: secs("connect_secs", raw_timeouts.connect_secs)?,
+        idle: secs("idle_secs", raw_timeouts.idle_secs)?,
+    };
+
+    let proxy_protocol = raw.proxy_protocol
+        .map(|v| v.parse::<ProxyProtocol>())
+        .transpose()?;
+    let forwarding = raw.forwarding
+        .map(|v| v.parse::<IpForwarding>())
+        .transpose()?;
+    let rewrite_handshake = match raw.rewrite_handshake {
+        Some(RawHandshakeRewrite::Target(target)) if target == HANDSHAKE_TARGET_UPSTREAM => Some(HandshakeRewrite::Upstream),
+        Some(RawHandshakeRewrite::Target(target)) => {
+            return Err(format!("rewrite_handshake: неизвестное значение '{}' (ожидается \"{}\" или {{ address, port }})", target, HANDSHAKE_TARGET_UPSTREAM));
+        }
+        Some(RawHandshakeRewrite::Fields(RawHandshakeFields { address, port })) => {
+            if let Some(address) = &address
+                && (address.is_empty() || address.contains('\0') || address.len() > MAX_STRING_LEN / 2)
+            {
+                return Err(format!("rewrite_handshake.address: некорректный адрес '{}'", address.escape_debug()));
+            }
+            if address.is_none() && port.is_none() {
+                return Err("rewrite_handshake: нужно задать address и/или port".to_string());
+            }
+            Some(HandshakeRewrite::Fixed { address, port })
+        }
+        None => None,
+    };
+
+    if let Some(motd) = &raw.motd && motd.len() > MAX_STRING_LEN / 2 {
+        return Err(format!("motd длиннее {} байт", MAX_STRING_LEN / 2));
+    }
+
+    let nets = |list: Vec<String>| -> Result<Vec<IpNet>, String> {
+        list.iter().map(|n| n.parse::<IpNet>()).collect()
+    };
+    let raw_access = raw.access.unwrap_or_default();
+    let access = AccessList {
+        allow: nets(raw_access.allow).map_err(|e| format!("access.allow: {}", e))?,
+        deny: nets(raw_access.deny).map_err(|e| format!("access.deny: {}", e))?,
+    };
+
+    let socket = raw.socket.unwrap_or_default();
+    socket.validate()?;
+
+    let transparent = raw.transparent.unwrap_or(false);
+    if transparent && socket.bind.is_some() {
+        return Err("transparent: подключения идут с адреса клиента, socket.bind не используется".to_string());
+    }
+    if transparent && !cfg!(target_os = "linux") {
+        return Err("transparent: поддерживается только в Linux".to_string());
+    }
+
+    Ok(RouteOptions {
+        rate_limit,
+        timeouts,
+        proxy_protocol,
+        motd: raw.motd,
+        access,
+        socket,
+        rewrite_handshake,
+        forwarding,
+        transparent,
+        splice: raw.splice.unwrap_or(false),
+    })
+}
+
+/// Формат файла конфига
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Формат по расширению: .toml, .yaml/.yml, иначе JSON
+    pub fn from_path(path: &str) -> Self {
+        let ext = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        match ext.as_str() {
+            "toml" => Self::Toml,
+            "yaml" | "yml" => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    /// Разобрать текст конфига в дерево значений
+    fn parse(self, data: &str) -> Result<Value, String> {
+        match self {
+            Self::Json => serde_json::from_str(data).map_err(|e| format!("Синтаксическая ошибка: {}", e)),
+            Self::Toml => {
+                let value: toml::Value = toml::from_str(data).map_err(|e| format!("Синтаксическая ошибка TOML: {}", e))?;
+                serde_json::to_value(value).map_err(|e| e.to_string())
+            }
+            Self::Yaml => serde_yaml::from_str(data).map_err(|e| format!("Синтаксическая ошибка YAML: {}", e)),
+        }
+    }
+
+    /// Записать дерево значений в этом формате
+    pub fn render(self, value: &Value) -> Result<String, String> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            Self::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            Self::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => Err(format!("неизвестный формат '{}' (json, toml или yaml)", s)),
+        }
+    }
+}
+
+/// Преобразовать конфиг устаревшего формата в документ версии 2
+fn migrate_legacy(legacy: LegacyConfig) -> Value {
+    let mut routes = Vec::new();
+    for (host, map) in legacy.endpoints {
+        for (domain, (tcp_port, udp_port)) in map {
+            routes.push(json!({ "name": domain, "upstream": host, "tcp_port": tcp_port, "udp_port": udp_port }));
+        }
+    }
+    routes.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+    json!({
+        "version": CONFIG_VERSION,
+        "listeners": {
+            "tcp": SocketAddr::from(([0, 0, 0, 0], legacy.tcp_port)),
+            "udp": SocketAddr::from(([0, 0, 0, 0], legacy.udp_port.unwrap_or(LEGACY_UDP_PORT))),
+        },
+        "routes": routes,
+    })
+}
+
+/// Переменные окружения, переопределяющие порты listeners
+const ENV_TCP_PORT: &str = "MC_PROXY_TCP_PORT";
+const ENV_UDP_PORT: &str = "MC_PROXY_UDP_PORT";
+
+/// Подставить `${NAME}` / `${NAME:-default}` из окружения в строку
+fn expand_env(s: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| format!("незакрытая подстановка в '{}'", s))?;
+            let expr = &after[..end];
+            let (name, default) = match expr.split_once(":-") {
+                Some((n, d)) => (n, Some(d)),
+                None => (expr, None),
+            };
+            match (std::env::var(name), default) {
+                (Ok(v), _) => out.push_str(&v),
+                (Err(_), Some(d)) => out.push_str(d),
+                (Err(_), None) => return Err(format!("переменная окружения {} не задана", name)),
+            }
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Подставить переменные окружения во все строковые значения (ключи не меняются)
+fn interpolate(value: &mut Value, path: &str) -> Result<(), String> {
+    match value {
+        Value::String(s) if s.contains('$') => {
+            let whole = s.starts_with("${") && s.ends_with('}') && s.matches("${").count() == 1;
+            let expanded = expand_env(s).map_err(|e| format!("{}: {}", path, e))?;
+            *value = match expanded.parse::<i64>() {
+                Ok(n) if whole => json!(n),
+                _ => match expanded.as_str() {
+                    "true" | "false" if whole => json!(expanded == "true"),
+                    _ => Value::String(expanded),
+                },
+            };
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                interpolate(item, &format!("{}[{}]", path, i))?;
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                interpolate(item, &path)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Прочитать файл конфига или фрагмента в дерево значений с подстановкой переменных окружения
+fn read_value(path: &str) -> Result<Value, String> {
     let data = fs::read_to_string(path).map_err(|e| format!("Невозможно прочитать {}: {}", path, e))?;
-    let cfg: Config = serde_json::from_str(&data).map_err(|e| format!("Синтаксическая ошибка: {}", e))?;
+    let mut value = ConfigFormat::from_path(path).parse(&data)?;
+    interpolate(&mut value, "").map_err(|e| format!("Ошибка подстановки: {}", e))?;
+    Ok(value)
+}
+
+/// Маршруты из фрагментов каталогов `include` (файлы .json/.toml/.yaml/.yml по имени).
+/// Возвращает пары (файл, маршрут); нечитаемые фрагменты попадают в `problems`.
+fn read_fragments(config_path: &str, include: &[String], problems: &mut Vec<String>) -> Vec<(String, Value)> {
+    let base = Path::new(config_path).parent().unwrap_or(Path::new("."));
+    let mut routes = Vec::new();
+    for dir in include {
+        let dir = base.join(dir);
+        let mut files: Vec<_> = match fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && matches!(
+                    p.extension().and_then(|e| e.to_str()),
+                    Some("json" | "toml" | "yaml" | "yml")
+                ))
+                .collect(),
+            Err(e) => {
+                problems.push(format!("Пропущен каталог {}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        files.sort();
+        for file in files {
+            let file = file.display().to_string();
+            let fragment = read_value(&file)
+                .and_then(|v| serde_json::from_value::<Fragment>(v).map_err(|e| e.to_string()));
+            match fragment {
+                Ok(f) => routes.extend(f.routes.into_iter().map(|r| (file.clone(), r))),
+                Err(e) => problems.push(format!("Пропущен фрагмент {}: {}", file, e)),
+            }
+        }
+    }
+    routes
+}
+
+/// Порт listener из переменной окружения, если она задана
+fn env_port(var: &str) -> Result<Option<u16>, String> {
+    match std::env::var(var) {
+        Ok(value) => value.parse().map(Some).map_err(|_| format!("{}: неверный порт '{}'", var, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Прочитать конфиг и привести его к текущей версии схемы
+fn read_document(path: &str) -> Result<Document, String> {
+    let mut value = read_value(path)?;
+
+    match value.get("version").map(|v| v.as_u64()) {
+        Some(Some(v)) if v == CONFIG_VERSION as u64 => {}
+        Some(_) => return Err(format!("Неподдерживаемая версия конфига (ожидается {})", CONFIG_VERSION)),
+        None if value.get("endpoints").is_some() => {
+            let legacy: LegacyConfig = serde_json::from_value(value)
+                .map_err(|e| format!("Ошибка в конфиге устаревшего формата: {}", e))?;
+            value = migrate_legacy(legacy);
+            eprintln!("\x1b[33mКонфиг {} в устаревшем формате преобразован в версию {}\x1b[0m", path, CONFIG_VERSION);
+        }
+        None => return Err(format!("Не указана версия конфига (version = {})", CONFIG_VERSION)),
+    }
+
+    serde_json::from_value(value).map_err(|e| format!("Ошибка в конфиге: {}", e))
+}
+
+/// Прочитать и проверить конфиг. Имена upstream здесь не разрешаются (см. `dns::RouteResolver`).
+/// В мягком режиме некорректные маршруты пропускаются и попадают в `problems`,
+/// в строгом — загрузка завершается ошибкой со списком всех проблем.
+pub fn load(path: &str, mode: LoadMode) -> Result<LoadedConfig, String> {
+    let config = load_entries(path)?;
+    if mode == LoadMode::Strict && !config.problems.is_empty() {
+        let mut err = format!("строгий режим, конфиг не применён. {}", config.summary());
+        for problem in &config.problems {
+            err.push_str("\n  - ");
+            err.push_str(problem);
+        }
+        return Err(err);
+    }
+    Ok(config)
+}
+
+fn load_entries(path: &str) -> Result<LoadedConfig, String> {
+    let mut doc = read_document(path)?;
+    let mut problems: Vec<String> = Vec::new();
+
+    if let Some(message) = &doc.shutdown.disconnect_message && message.len() > MAX_STRING_LEN / 2 {
+        return Err(format!("shutdown.disconnect_message длиннее {} байт", MAX_STRING_LEN / 2));
+    }
+    if doc.limits.max_sessions == Some(0) {
+        return Err("limits.max_sessions должен быть больше 0".to_string());
+    }
+    doc.listeners.socket.validate().map_err(|e| format!("listeners.{}", e))?;
+    if doc.listeners.socket.bind.is_some() {
+        return Err("listeners.socket.bind: локальный адрес задаётся только для подключений к upstream (options.socket маршрута)".to_string());
+    }
+    if let Some(frontend) = &doc.listeners.frontend {
+        Frontend::new(frontend).map_err(|e| format!("listeners.frontend.{}", e))?;
+    }
+
+    if let Some(port) = env_port(ENV_TCP_PORT)? {
+        doc.listeners.tcp.set_port(port);
+    }
+    // UDP включается переменной, даже если listeners.udp не задан
+    if let Some(port) = env_port(ENV_UDP_PORT)? {
+        doc.listeners.udp.get_or_insert(SocketAddr::from(([0, 0, 0, 0], port))).set_port(port);
+    }
+
+    // Маршруты основного файла, затем фрагментов (источник нужен для сообщений)
+    let fragments = read_fragments(path, &doc.include, &mut problems);
+    let entries: Vec<(Option<String>, Value)> = doc.routes.into_iter().map(|r| (None, r))
+        .chain(fragments.into_iter().map(|(file, r)| (Some(file), r)))
+        .collect();
 
     // Регекс для проверки имени домена (латиница + цифры)
     let domain_re = Regex::new(r"^[A-Za-z0-9]+$").unwrap();
+    // Имя хоста upstream: метки из латиницы, цифр и дефисов через точку
+    let hostname_re = Regex::new(r"^[a-z0-9-]{1,63}(\.[a-z0-9-]{1,63})*$").unwrap();
 
     // Наборы для проверки дубликатов
-    let mut seen_dest_addrs: HashSet<SocketAddr> = HashSet::new();
+    let mut seen_dest: HashSet<(Upstream, u16)> = HashSet::new();
+    let mut seen_names: HashSet<String> = HashSet::new();
 
-    let mut desired: HashMap<String, (String, String)> = HashMap::new();
+    let mut routes: Vec<RouteSpec> = Vec::with_capacity(entries.len());
 
-    println!("\x1b[1;32m{}\x1b[0m", "Валидация конфига");
-    for (host, map) in cfg.endpoints {
-        // валидируем IP один раз
-        let ip = match valid_ip(&host) {
-            Some(ip) => ip,
-            None => {
-                eprintln!("\x1b[33m{}\x1b[0m", format!("Пропущен узел: '{}' - некорректный IP", host));
+    for (i, (source, value)) in entries.into_iter().enumerate() {
+        let from = source.map(|f| format!(" [{}]", f)).unwrap_or_default();
+        let label = match value.get("name").and_then(Value::as_str) {
+            Some(name) => format!("'{}'", name),
+            None => format!("#{}", i + 1),
+        };
+        let raw: RawRoute = match serde_json::from_value(value) {
+            Ok(r) => r,
+            Err(e) => {
+                problems.push(format!("Пропущен маршрут {}: {}{}", label, e, from));
                 continue;
             }
         };
 
-        for (domain, ports) in map {
-            if !&domain_re.is_match(&domain) {
-                eprintln!("\x1b[33m{}\x1b[0m", format!("Пропущен маршрут для {}: неверное имя поддомена '{}'", host, domain));
+        if !domain_re.is_match(&raw.name) {
+            problems.push(format!("Пропущен маршрут для {}: неверное имя поддомена '{}'{}", raw.upstream, raw.name, from));
+            continue;
+        }
+        // Маршрут ищется по имени без учёта регистра
+        if seen_names.contains(&raw.name.to_ascii_lowercase()) {
+            problems.push(format!("Пропущен маршрут '{}': имя уже используется{}", raw.name, from));
+            continue;
+        }
+
+        let upstream = match parse_upstream(&raw.upstream, &hostname_re) {
+            Ok(u) => u,
+            Err(e) => {
+                problems.push(format!("Пропущен маршрут '{}': узел '{}' - {}{}", raw.name, raw.upstream, e, from));
                 continue;
             }
+        };
 
-            let tcp_sock = SocketAddr::new(ip, ports.0);
-            let udp_sock = SocketAddr::new(ip, ports.1);
-
-            // Проверка дубликатов портов назначения (по SocketAddr)
-            if seen_dest_addrs.contains(&tcp_sock) {
-                eprintln!("\x1b[33m{}\x1b[0m", format!("Skipping {}:{} — tcp destination {} already used", host, domain, tcp_sock));
+        let options = match parse_options(raw.options.merged(&doc.defaults.options)) {
+            Ok(o) => o,
+            Err(e) => {
+                problems.push(format!("Пропущен маршрут '{}': {}{}", raw.name, e, from));
                 continue;
             }
-            if seen_dest_addrs.contains(&udp_sock) {
-                eprintln!("\x1b[33m{}\x1b[0m", format!("Skipping {}:{} — udp destination {} already used", host, domain, udp_sock));
-                continue;
+        };
+
+        // Проверка дубликатов портов назначения на одном узле
+        // (совпадения после разрешения имён проверяет RouteResolver)
+        let tcp_dest = (upstream.clone(), raw.tcp_port);
+        let udp_dest = raw.udp_port.map(|p| (upstream.clone(), p));
+        if seen_dest.contains(&tcp_dest) {
+            problems.push(format!("Skipping {}:{} — tcp destination {}:{} already used{}", raw.upstream, raw.name, upstream, raw.tcp_port, from));
+            continue;
+        }
+        if let Some(dest) = &udp_dest && seen_dest.contains(dest) {
+            problems.push(format!("Skipping {}:{} — udp destination {}:{} already used{}", raw.upstream, raw.name, upstream, dest.1, from));
+            continue;
+        }
+
+        // Всё ок — помечаем как использованные и добавляем
+        seen_dest.insert(tcp_dest);
+        seen_dest.extend(udp_dest);
+        seen_names.insert(raw.name.to_ascii_lowercase());
+
+        routes.push(RouteSpec { name: raw.name, upstream, tcp_port: raw.tcp_port, udp_port: raw.udp_port, options });
+    }
+
+    routes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(LoadedConfig { listeners: doc.listeners, shutdown: doc.shutdown, limits: doc.limits, routes, problems })
+}
+
+/// Итоговый конфиг в схеме текущей версии: после миграции, с подставленными значениями
+/// по умолчанию и без пропущенных маршрутов
+pub fn effective_document(config: &LoadedConfig) -> Value {
+    let routes: Vec<Value> = config.routes.iter().map(|r| {
+        let mut route = json!({
+            "name": r.name,
+            "upstream": r.upstream.to_string(),
+            "tcp_port": r.tcp_port,
+        });
+        if let Some(udp) = r.udp_port {
+            route["udp_port"] = json!(udp);
+        }
+        let options = RawOptions::from(&r.options);
+        let options = serde_json::to_value(options).unwrap_or_default();
+        if options.as_object().is_some_and(|o| !o.is_empty()) {
+            route["options"] = options;
+        }
+        route
+    }).collect();
+
+    json!({
+        "version": CONFIG_VERSION,
+        "listeners": config.listeners,
+        "shutdown": config.shutdown,
+        "limits": config.limits,
+        "routes": routes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Тесты меняют переменные окружения процесса: выполняются по одному
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn set_env(vars: &[(&str, Option<&str>)]) {
+        for (name, value) in vars {
+            // SAFETY: другие тесты не читают эти переменные, пока держится ENV
+            unsafe {
+                match value {
+                    Some(v) => std::env::set_var(name, v),
+                    None => std::env::remove_var(name),
+                }
             }
+        }
+    }
 
-            // Всё ок — помечаем как использованные и добавляем
-            seen_dest_addrs.insert(tcp_sock);
-            seen_dest_addrs.insert(udp_sock);
+    /// Временный каталог с файлами конфига, удаляется при drop
+    struct TempDir(PathBuf);
 
-            let tcp_addr = tcp_sock.to_string();
-            let udp_addr = udp_sock.to_string();
-            desired.insert(domain.clone(), (tcp_addr, udp_addr));
+    impl TempDir {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!("mc-proxy-test-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, data: &str) -> String {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, data).unwrap();
+            path.display().to_string()
         }
     }
 
-    println!("\x1b[1;32m{}\x1b[0m", "Добавление валидных маршрутов");
-    for (name, (tcp_addr, udp_addr)) in &desired {
-        router.add_route(name.clone(), tcp_addr.clone(), udp_addr.clone());
-        println!("Добавлен домен '{}'\n> tcp:{}\n> udp:{}", name, tcp_addr, udp_addr);
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn route<'a>(config: &'a LoadedConfig, name: &str) -> &'a RouteSpec {
+        config.routes.iter().find(|r| r.name == name).unwrap()
+    }
+
+    #[test]
+    fn legacy_config_is_migrated() {
+        let dir = TempDir::new();
+        let path = dir.write("proxy.json", r#"{
+            "tcp_port": 25526,
+            "udp_port": 24455,
+            "endpoints": {
+                "185.24.55.33": { "fractal": [54636, 24454], "lobby": [54637, 24456] },
+                "10.0.0.2": { "survival": [25565, 24454] }
+            }
+        }"#);
+        let config = load(&path, LoadMode::Strict).unwrap();
+        assert_eq!(config.listeners.tcp, "0.0.0.0:25526".parse().unwrap());
+        assert_eq!(config.listeners.udp, Some("0.0.0.0:24455".parse().unwrap()));
+        let names: Vec<_> = config.routes.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["fractal", "lobby", "survival"]);
+        let fractal = route(&config, "fractal");
+        assert_eq!(fractal.upstream, Upstream::Ip("185.24.55.33".parse().unwrap()));
+        assert_eq!((fractal.tcp_port, fractal.udp_port), (54636, Some(24454)));
+        assert_eq!(route(&config, "survival").upstream, Upstream::Ip("10.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn legacy_config_without_udp_port_listens_on_default() {
+        let dir = TempDir::new();
+        let path = dir.write("proxy.json", r#"{ "tcp_port": 25565, "endpoints": { "185.24.55.33": { "fractal": [54636, 24454] } } }"#);
+        let config = load(&path, LoadMode::Strict).unwrap();
+        assert_eq!(config.listeners.udp, Some(SocketAddr::from(([0, 0, 0, 0], LEGACY_UDP_PORT))));
+        assert_eq!(config.routes.len(), 1);
+    }
+
+    #[test]
+    fn legacy_config_rejects_other_shapes() {
+        let dir = TempDir::new();
+        for endpoint in [r#"{ "tcp": 54636, "udp": 24454 }"#, "54636", "[54636]"] {
+            let path = dir.write("proxy.json", &format!(r#"{{ "tcp_port": 25565, "endpoints": {{ "185.24.55.33": {{ "fractal": {} }} }} }}"#, endpoint));
+            let err = load(&path, LoadMode::Lenient).err().unwrap();
+            assert!(err.contains("устаревшего формата"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn env_substitution() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        set_env(&[("MC_PROXY_TEST_HOST", Some("10.0.0.5")), ("MC_PROXY_TEST_UNSET", None)]);
+
+        assert_eq!(expand_env("${MC_PROXY_TEST_HOST}").unwrap(), "10.0.0.5");
+        assert_eq!(expand_env("tcp://${MC_PROXY_TEST_HOST}:25565").unwrap(), "tcp://10.0.0.5:25565");
+        assert_eq!(expand_env("${MC_PROXY_TEST_UNSET:-127.0.0.1}").unwrap(), "127.0.0.1");
+        assert_eq!(expand_env("${MC_PROXY_TEST_HOST:-127.0.0.1}").unwrap(), "10.0.0.5");
+        assert_eq!(expand_env("${MC_PROXY_TEST_UNSET:-}").unwrap(), "");
+        assert!(expand_env("${MC_PROXY_TEST_UNSET}").unwrap_err().contains("MC_PROXY_TEST_UNSET"));
+        assert!(expand_env("${MC_PROXY_TEST_HOST").unwrap_err().contains("незакрытая"));
+
+        // $${ — литерал, одиночный $ остаётся как есть
+        assert_eq!(expand_env("$${MC_PROXY_TEST_HOST}").unwrap(), "${MC_PROXY_TEST_HOST}");
+        assert_eq!(expand_env("cost $5").unwrap(), "cost $5");
+        set_env(&[("MC_PROXY_TEST_HOST", None)]);
     }
 
-    Ok(cfg.tcp_port)
+    #[test]
+    fn interpolation_types_whole_values() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        set_env(&[("MC_PROXY_TEST_PORT", Some("25566")), ("MC_PROXY_TEST_FLAG", Some("true"))]);
+
+        let mut value = json!({
+            "tcp_port": "${MC_PROXY_TEST_PORT}",
+            "label": "port ${MC_PROXY_TEST_PORT}",
+            "options": { "splice": "${MC_PROXY_TEST_FLAG}" },
+            "list": ["${MC_PROXY_TEST_PORT}", "$${MC_PROXY_TEST_PORT}"],
+            "${MC_PROXY_TEST_PORT}": 1,
+        });
+        interpolate(&mut value, "").unwrap();
+        assert_eq!(value, json!({
+            "tcp_port": 25566,
+            "label": "port 25566",
+            "options": { "splice": true },
+            "list": [25566, "${MC_PROXY_TEST_PORT}"],
+            "${MC_PROXY_TEST_PORT}": 1,
+        }));
+
+        // Ошибка указывает путь к значению
+        let mut value = json!({ "routes": [{ "upstream": "${MC_PROXY_TEST_MISSING}" }] });
+        set_env(&[("MC_PROXY_TEST_MISSING", None)]);
+        assert!(interpolate(&mut value, "").unwrap_err().starts_with("routes[0].upstream:"));
+        set_env(&[("MC_PROXY_TEST_PORT", None), ("MC_PROXY_TEST_FLAG", None)]);
+    }
+
+    #[test]
+    fn fragments_merge_in_include_then_file_order() {
+        let dir = TempDir::new();
+        let fragment = |name: &str| format!(r#"{{ "routes": [{{ "name": "{}", "upstream": "10.0.0.1", "tcp_port": 1 }}] }}"#, name);
+        dir.write("b.d/10-b.json", &fragment("b10"));
+        dir.write("a.d/20-a.yaml", "routes:\n  - { name: a20, upstream: 10.0.0.1, tcp_port: 2 }\n");
+        dir.write("a.d/10-a.toml", "[[routes]]\nname = \"a10\"\nupstream = \"10.0.0.1\"\ntcp_port = 3\n");
+        dir.write("a.d/README.txt", "не фрагмент");
+        dir.write("a.d/30-broken.json", "{ \"routes\": [");
+        let config = dir.write("proxy.json", "{}");
+
+        let mut problems = Vec::new();
+        let include = ["b.d".to_string(), "a.d".to_string(), "missing.d".to_string()];
+        let routes = read_fragments(&config, &include, &mut problems);
+        let names: Vec<_> = routes.iter().map(|(_, r)| r["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["b10", "a10", "a20"]);
+        assert!(routes[0].0.ends_with("10-b.json"));
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("30-broken.json"));
+        assert!(problems[1].contains("missing.d"));
+    }
+
+    #[test]
+    fn main_file_routes_take_precedence_over_fragments() {
+        let dir = TempDir::new();
+        dir.write("conf.d/lobby.json", r#"{ "routes": [
+            { "name": "lobby", "upstream": "10.0.0.9", "tcp_port": 25565 },
+            { "name": "survival", "upstream": "10.0.0.2", "tcp_port": 25565 }
+        ] }"#);
+        let path = dir.write("proxy.json", r#"{
+            "version": 2,
+            "listeners": { "tcp": "0.0.0.0:25565" },
+            "include": ["conf.d"],
+            "routes": [{ "name": "lobby", "upstream": "10.0.0.1", "tcp_port": 25565 }]
+        }"#);
+        let config = load(&path, LoadMode::Lenient).unwrap();
+        assert_eq!(route(&config, "lobby").upstream, Upstream::Ip("10.0.0.1".parse().unwrap()));
+        assert_eq!(route(&config, "survival").upstream, Upstream::Ip("10.0.0.2".parse().unwrap()));
+        assert_eq!(config.problems.len(), 1);
+        assert!(config.problems[0].contains("lobby.json"));
+    }
+
+    const MIXED_ROUTES: &str = r#"{
+        "version": 2,
+        "listeners": { "tcp": "0.0.0.0:25565" },
+        "routes": [
+            { "name": "lobby", "upstream": "10.0.0.1", "tcp_port": 25565 },
+            { "name": "bad-name", "upstream": "10.0.0.2", "tcp_port": 25565 },
+            { "name": "typo", "upstream": "10.0.0.3", "tcp_port": 25565, "udp_prot": 24454 },
+            { "name": "survival", "upstream": "mc.example.com", "tcp_port": 25566 }
+        ]
+    }"#;
+
+    #[test]
+    fn strict_mode_rejects_whole_config() {
+        let dir = TempDir::new();
+        let path = dir.write("proxy.json", MIXED_ROUTES);
+        let err = load(&path, LoadMode::Strict).err().unwrap();
+        assert!(err.starts_with("строгий режим"), "{}", err);
+        assert!(err.contains("Маршрутов принято: 2, отклонено: 2"), "{}", err);
+        assert!(err.contains("bad-name"));
+        assert!(err.contains("udp_prot"));
+    }
+
+    #[test]
+    fn lenient_mode_skips_bad_routes_and_keeps_rest() {
+        let dir = TempDir::new();
+        let path = dir.write("proxy.json", MIXED_ROUTES);
+        let config = load(&path, LoadMode::Lenient).unwrap();
+        let names: Vec<_> = config.routes.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["lobby", "survival"]);
+        assert_eq!(route(&config, "survival").upstream, Upstream::Host("mc.example.com".to_string()));
+        assert_eq!(config.problems.len(), 2);
+        assert!(config.problems.iter().any(|p| p.contains("udp_prot")));
+    }
+
+    #[test]
+    fn unknown_document_key_fails_in_both_modes() {
+        let dir = TempDir::new();
+        let path = dir.write("proxy.json", r#"{ "version": 2, "listeners": { "tcp": "0.0.0.0:25565" }, "rotues": [] }"#);
+        for mode in [LoadMode::Strict, LoadMode::Lenient] {
+            assert!(load(&path, mode).err().unwrap().contains("rotues"));
+        }
+    }
+
+    fn options(raw: Value) -> Result<RouteOptions, String> {
+        options_with_defaults(raw, json!({}))
+    }
+
+    fn options_with_defaults(raw: Value, defaults: Value) -> Result<RouteOptions, String> {
+        let raw: RawOptions = serde_json::from_value(raw).map_err(|e| e.to_string())?;
+        let defaults: RawOptions = serde_json::from_value(defaults).map_err(|e| e.to_string())?;
+        parse_options(raw.merged(&defaults))
+    }
+
+    #[test]
+    fn route_options_are_parsed() {
+        let parsed = options(json!({
+            "timeouts": { "connect_secs": 5, "idle_secs": 600 },
+            "rate_limit": { "bytes_per_sec": 1000 },
+            "access": { "allow": ["192.0.2.0/24", "2001:db8::/32"], "deny": ["192.0.2.13"] },
+            "motd": "§cСервер на обслуживании",
+            "proxy_protocol": "v2",
+            "forwarding": "bungeecord",
+        })).unwrap();
+        assert_eq!(parsed.timeouts.connect, Some(Duration::from_secs(5)));
+        assert_eq!(parsed.timeouts.idle, Some(Duration::from_secs(600)));
+        let rate_limit = parsed.rate_limit.unwrap();
+        // burst по умолчанию — два bytes_per_sec
+        assert_eq!((rate_limit.bytes_per_sec, rate_limit.burst_bytes), (1000, 2000));
+        assert!(parsed.access.permits(&"192.0.2.7".parse().unwrap()));
+        assert!(!parsed.access.permits(&"192.0.2.13".parse().unwrap()));
+        assert!(!parsed.access.permits(&"198.51.100.1".parse().unwrap()));
+        assert!(parsed.access.permits(&"2001:db8::1".parse().unwrap()));
+        assert_eq!(parsed.motd.as_deref(), Some("§cСервер на обслуживании"));
+        assert_eq!(parsed.proxy_protocol, Some(ProxyProtocol::V2));
+        assert_eq!(parsed.forwarding, Some(IpForwarding::BungeeCord));
+
+        let empty = options(json!({})).unwrap();
+        assert!(empty.rate_limit.is_none() && empty.timeouts.connect.is_none() && empty.forwarding.is_none());
+        assert!(empty.access.permits(&"198.51.100.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn route_options_fall_back_to_defaults_field_by_field() {
+        let parsed = options_with_defaults(
+            json!({ "timeouts": { "idle_secs": 60 }, "motd": "route" }),
+            json!({ "timeouts": { "connect_secs": 3, "idle_secs": 300 }, "motd": "default", "proxy_protocol": "v1" }),
+        ).unwrap();
+        assert_eq!(parsed.timeouts.connect, Some(Duration::from_secs(3)));
+        assert_eq!(parsed.timeouts.idle, Some(Duration::from_secs(60)));
+        assert_eq!(parsed.motd.as_deref(), Some("route"));
+        assert_eq!(parsed.proxy_protocol, Some(ProxyProtocol::V1));
+    }
+
+    #[test]
+    fn invalid_route_options_are_rejected() {
+        let invalid = [
+            json!({ "timeouts": { "connect_secs": 0 } }),
+            json!({ "timeouts": { "idle_secs": -1 } }),
+            json!({ "timeouts": { "read_secs": 5 } }),
+            json!({ "rate_limit": { "bytes_per_sec": 0 } }),
+            json!({ "rate_limit": { "bytes_per_sec": 1000, "burst_bytes": 0 } }),
+            json!({ "access": { "allow": ["192.0.2.0/33"] } }),
+            json!({ "access": { "deny": ["не адрес"] } }),
+            json!({ "motd": "x".repeat(MAX_STRING_LEN) }),
+            json!({ "proxy_protocol": "v3" }),
+            json!({ "forwarding": "velocity" }),
+            json!({ "rewrite_handshake": "downstream" }),
+            json!({ "rewrite_handshake": {} }),
+            json!({ "socket": { "dscp": 64 } }),
+            json!({ "transparent": true, "socket": { "bind": "10.0.0.2" } }),
+        ];
+        for raw in invalid {
+            assert!(options(raw.clone()).is_err(), "{}", raw);
+        }
+    }
 }
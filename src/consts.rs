@@ -2,7 +2,60 @@ use tokio::time::Duration;
 
 pub const MAX_PACKET_LEN: usize = 256 * 1024;
 pub const MAX_STRING_LEN: usize = 32 * 1024;
+/// Допустимое расхождение метки времени фронта (TCPShield и т.п.) с часами прокси
+pub const FRONTEND_MAX_AGE: Duration = Duration::from_secs(30);
 pub const HANDSHAKE_READ_TIMEOUT: Duration = Duration::from_secs(5);
+pub const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const FORWARD_BUF_SIZE: usize = 16 * 1024;
+/// Буфер pipe для splice: сколько байт за раз переносится из сокета в сокет
+pub const SPLICE_PIPE_SIZE: usize = 64 * 1024;
+/// Сколько ждать завершения активных сессий при остановке
+pub const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Сколько ждать готовности нового процесса при обновлении бинаря
+pub const HANDOFF_READY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Как часто писать отчёт о приёме подключений и сообщать systemd статус
+/// (и watchdog, если его период не короче)
+pub const STATUS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+// Пауза приёма подключений при нехватке дескрипторов: удваивается до максимума
+pub const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+pub const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+// Перезапуск упавших фоновых подсистем: пауза удваивается до максимума; после
+// SUPERVISOR_MAX_FAILURES сбоев подряд прокси считается неисправным, пока подсистема
+// не проработает без сбоев SUPERVISOR_STABLE_AFTER
+pub const SUPERVISOR_BACKOFF_MIN: Duration = Duration::from_secs(1);
+pub const SUPERVISOR_BACKOFF_MAX: Duration = Duration::from_secs(60);
+pub const SUPERVISOR_MAX_FAILURES: u32 = 5;
+pub const SUPERVISOR_STABLE_AFTER: Duration = Duration::from_secs(60);
 
 pub const DEFAULT_BYTES_PER_SEC: usize = 64 * 1024;
-pub const DEFAULT_BURST_BYTES: usize = 128 * 1024;
\ No newline at end of file
+pub const DEFAULT_BURST_BYTES: usize = 128 * 1024;
+
+pub const UDP_FLOW_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+pub const UDP_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+pub const UDP_SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+/// Сколько UDP сессия живёт после завершения своей TCP сессии (переподключение без обрыва голоса)
+pub const UDP_SESSION_GRACE: Duration = Duration::from_secs(15);
+pub const UDP_MAX_FLOWS: usize = 4096;
+pub const UDP_MAX_FLOWS_PER_IP: usize = 16;
+pub const UDP_MAX_FLOWS_PER_PLAYER: usize = 4;
+
+// Лимиты UDP потока: клиент -> upstream (голос одного игрока ~50 пакетов/с)
+pub const UDP_PACKETS_PER_SEC: usize = 200;
+pub const UDP_PACKETS_BURST: usize = 400;
+pub const UDP_BYTES_PER_SEC: usize = 64 * 1024;
+pub const UDP_BURST_BYTES: usize = 128 * 1024;
+// upstream -> клиент: сервер пересылает голоса всех игроков рядом
+pub const UDP_REPLY_PACKETS_PER_SEC: usize = 1000;
+pub const UDP_REPLY_PACKETS_BURST: usize = 2000;
+pub const UDP_REPLY_BYTES_PER_SEC: usize = 256 * 1024;
+pub const UDP_REPLY_BURST_BYTES: usize = 512 * 1024;
+
+// Пакетная обработка UDP: датаграмм за один recvmmsg/sendmmsg и размер слота буфера
+pub const UDP_BATCH_SIZE: usize = 32;
+pub const UDP_REPLY_BATCH_SIZE: usize = 8;
+pub const UDP_MAX_DATAGRAM: usize = 4096;
+
+// Повторное разрешение DNS имён upstream: TTL записи ограничивается этими пределами
+pub const DNS_MIN_REFRESH: Duration = Duration::from_secs(5);
+pub const DNS_MAX_REFRESH: Duration = Duration::from_secs(5 * 60);
+pub const DNS_RETRY_INTERVAL: Duration = Duration::from_secs(10);
@@ -0,0 +1,465 @@
+use hickory_resolver::TokioResolver;
+use hickory_resolver::proto::rr::rdata::SRV;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+
+use crate::configure::{valid_ip, RouteSpec, Upstream, SRV_PREFIX};
+use crate::proto::{Route, Router};
+use crate::consts::{DNS_MAX_REFRESH, DNS_MIN_REFRESH, DNS_RETRY_INTERVAL};
+
+/// Результат DNS запроса и момент, до которого он действителен (TTL)
+pub struct Resolved<T> {
+    pub value: T,
+    pub valid_until: Instant,
+}
+
+#[derive(Clone, Debug)]
+pub struct SrvTarget {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// Источник DNS ответов. Системная реализация — `SystemResolver`; для проверок можно подставить заглушку.
+pub trait Resolver: Send + Sync + 'static {
+    fn lookup_ip(&self, host: &str) -> impl Future<Output = Result<Resolved<Vec<IpAddr>>, String>> + Send;
+    /// Пустой список — записей нет (это не ошибка)
+    fn lookup_srv(&self, name: &str) -> impl Future<Output = Result<Resolved<Vec<SrvTarget>>, String>> + Send;
+}
+
+/// Резолвер по системной конфигурации (/etc/resolv.conf и /etc/hosts)
+pub struct SystemResolver {
+    inner: TokioResolver,
+}
+
+impl SystemResolver {
+    pub fn new() -> Result<Self, String> {
+        let inner = TokioResolver::builder_tokio()
+            .map_err(|e| format!("не удалось прочитать системные настройки DNS: {}", e))?
+            .build();
+        Ok(Self { inner })
+    }
+}
+
+impl Resolver for SystemResolver {
+    async fn lookup_ip(&self, host: &str) -> Result<Resolved<Vec<IpAddr>>, String> {
+        let lookup = self.inner.lookup_ip(host).await.map_err(|e| e.to_string())?;
+        Ok(Resolved {
+            value: lookup.iter().collect(),
+            valid_until: Instant::from_std(lookup.valid_until()),
+        })
+    }
+
+    async fn lookup_srv(&self, name: &str) -> Result<Resolved<Vec<SrvTarget>>, String> {
+        match self.inner.srv_lookup(name).await {
+            Ok(lookup) => Ok(Resolved {
+                value: lookup.iter().map(srv_target).collect(),
+                valid_until: Instant::from_std(lookup.as_lookup().valid_until()),
+            }),
+            Err(e) if e.is_no_records_found() => Ok(Resolved {
+                value: Vec::new(),
+                valid_until: Instant::now() + DNS_RETRY_INTERVAL,
+            }),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+fn srv_target(srv: &SRV) -> SrvTarget {
+    SrvTarget {
+        priority: srv.priority(),
+        weight: srv.weight(),
+        port: srv.port(),
+        target: srv.target().to_utf8().trim_end_matches('.').to_ascii_lowercase(),
+    }
+}
+
+/// Разрешённые адреса маршрута
+#[derive(Clone, PartialEq, Eq)]
+struct Resolution {
+    tcp: SocketAddr,
+    udp: Option<SocketAddr>,
+    /// Имя, в которое фактически разрешался адрес (цель SRV или имя хоста)
+    host: Option<String>,
+    /// None — адрес задан IP и не переразрешается
+    valid_until: Option<Instant>,
+}
+
+/// Разрешает upstream маршрутов из конфига и публикует их в Router.
+/// Имена переразрешаются по истечении TTL; при изменении адресов набор маршрутов
+/// в Router атомарно заменяется. Если имя временно не разрешается, маршрут
+/// сохраняет последний известный адрес.
+pub struct RouteResolver<R> {
+    router: Arc<Router>,
+    resolver: R,
+    specs: Vec<RouteSpec>,
+    resolved: HashMap<String, Resolution>,
+    next_refresh: Instant,
+    /// Набор маршрутов заменён: Router обновляется при следующем `sync`, даже если адреса те же
+    republish: bool,
+}
+
+impl<R: Resolver> RouteResolver<R> {
+    pub fn new(router: Arc<Router>, resolver: R, specs: Vec<RouteSpec>) -> Self {
+        Self { router, resolver, specs, resolved: HashMap::new(), next_refresh: Instant::now(), republish: true }
+    }
+
+    /// Переразрешать имена по мере истечения TTL и применять новые наборы маршрутов
+    /// (перезагрузка конфига), пока открыт канал `updates`
+    pub async fn run(mut self, mut updates: mpsc::Receiver<Vec<RouteSpec>>) {
+        loop {
+            tokio::select! {
+                _ = sleep_until(self.next_refresh) => {}
+                specs = updates.recv() => match specs {
+                    Some(specs) => self.set_specs(specs),
+                    None => return,
+                },
+            }
+            for problem in self.sync().await {
+                eprintln!("\x1b[33m{}\x1b[0m", problem);
+            }
+        }
+    }
+
+    /// Заменить набор маршрутов: все имена разрешаются заново при следующем `sync`.
+    /// Маршрут с прежними именем и узлом до тех пор остаётся на последнем известном адресе,
+    /// чтобы сбой DNS во время перезагрузки не убрал работающие маршруты
+    pub fn set_specs(&mut self, specs: Vec<RouteSpec>) {
+        let now = Instant::now();
+        let previous: HashMap<String, Upstream> = self.specs.drain(..).map(|s| (s.name, s.upstream)).collect();
+        let mut resolved = std::mem::take(&mut self.resolved);
+        for spec in &specs {
+            // Адрес из IP разрешается без DNS
+            if matches!(spec.upstream, Upstream::Ip(_)) || previous.get(&spec.name) != Some(&spec.upstream) {
+                continue;
+            }
+            let Some(mut res) = resolved.remove(&spec.name) else { continue };
+            // Порты берутся из нового конфига (TCP порт цели SRV — из записи)
+            let ip = res.tcp.ip();
+            if let Upstream::Host(_) = spec.upstream {
+                res.tcp.set_port(spec.tcp_port);
+            }
+            res.udp = spec.udp_port.map(|p| SocketAddr::new(ip, p));
+            res.valid_until = Some(now);
+            self.resolved.insert(spec.name.clone(), res);
+        }
+        self.specs = specs;
+        self.next_refresh = now;
+        self.republish = true;
+    }
+
+    /// Разрешить устаревшие записи и обновить Router, если адреса изменились.
+    /// Возвращает проблемы этого прохода: ошибки разрешения и дубликаты адресов.
+    pub async fn sync(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let now = Instant::now();
+        let mut next = now + DNS_MAX_REFRESH;
+        let mut changed = std::mem::take(&mut self.republish);
+
+        for spec in &self.specs {
+            let prev = self.resolved.get(&spec.name);
+            if let Some(prev) = prev {
+                match prev.valid_until {
+                    None => continue,
+                    Some(until) if until > now => {
+                        next = next.min(until);
+                        continue;
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            match self.resolve(spec, prev).await {
+                Ok(res) => {
+                    if let Some(until) = res.valid_until {
+                        next = next.min(until);
+                    }
+                    if let Some(prev) = prev && (prev.tcp, prev.udp) != (res.tcp, res.udp) {
+                        println!("DNS: маршрут '{}' ({}) {} -> {}", spec.name, spec.upstream, prev.tcp, res.tcp);
+                    }
+                    changed |= prev.is_none_or(|p| (p.tcp, p.udp) != (res.tcp, res.udp));
+                    self.resolved.insert(spec.name.clone(), res);
+                }
+                Err(e) => {
+                    next = next.min(now + DNS_RETRY_INTERVAL);
+                    match prev {
+                        Some(prev) => problems.push(format!("DNS: не удалось разрешить {} для '{}': {}; остаётся {}", spec.upstream, spec.name, e, prev.tcp)),
+                        None => problems.push(format!("DNS: не удалось разрешить {} для '{}': {}; маршрут пропущен до следующей попытки", spec.upstream, spec.name, e)),
+                    }
+                }
+            }
+        }
+
+        self.next_refresh = next;
+        if changed {
+            self.publish(&mut problems);
+        }
+        problems
+    }
+
+    async fn resolve(&self, spec: &RouteSpec, prev: Option<&Resolution>) -> Result<Resolution, String> {
+        let (host, tcp_port, srv_until) = match &spec.upstream {
+            Upstream::Ip(ip) => {
+                return Ok(Resolution {
+                    tcp: SocketAddr::new(*ip, spec.tcp_port),
+                    udp: spec.udp_port.map(|p| SocketAddr::new(*ip, p)),
+                    host: None,
+                    valid_until: None,
+                });
+            }
+            Upstream::Host(host) => (host.clone(), spec.tcp_port, None),
+            Upstream::Srv(name) => {
+                let srv = self.resolver.lookup_srv(name).await?;
+                // Как клиент Minecraft: наименьший priority, среди них наибольший weight;
+                // без записей — сам домен с портом из конфига
+                match srv.value.iter().min_by_key(|t| (t.priority, std::cmp::Reverse(t.weight))) {
+                    Some(t) => (t.target.clone(), t.port, Some(srv.valid_until)),
+                    None => (name.trim_start_matches(SRV_PREFIX).to_string(), spec.tcp_port, Some(srv.valid_until)),
+                }
+            }
+        };
+
+        let ips = self.resolver.lookup_ip(&host).await?;
+        let usable: Vec<IpAddr> = ips.value.into_iter().filter(|ip| valid_ip(&ip.to_string()).is_some()).collect();
+        // Текущий адрес сохраняется, пока он среди ответов: round-robin DNS не переключает маршрут
+        let ip = match prev.map(|p| p.tcp.ip()).filter(|ip| usable.contains(ip)) {
+            Some(ip) => ip,
+            None => *usable.first().ok_or_else(|| format!("{} не разрешается в пригодный IP", host))?,
+        };
+
+        let now = Instant::now();
+        let until = srv_until.map_or(ips.valid_until, |s| s.min(ips.valid_until));
+        Ok(Resolution {
+            tcp: SocketAddr::new(ip, tcp_port),
+            udp: spec.udp_port.map(|p| SocketAddr::new(ip, p)),
+            host: Some(host),
+            valid_until: Some(until.clamp(now + DNS_MIN_REFRESH, now + DNS_MAX_REFRESH)),
+        })
+    }
+
+    /// Собрать маршруты из разрешённых адресов и заменить ими набор в Router
+    fn publish(&self, problems: &mut Vec<String>) {
+        let mut seen_dest_addrs: HashSet<SocketAddr> = HashSet::new();
+        let mut routes = Vec::with_capacity(self.specs.len());
+
+        println!("\x1b[1;32mДобавление валидных маршрутов\x1b[0m");
+        for spec in &self.specs {
+            let Some(res) = self.resolved.get(&spec.name) else { continue };
+
+            // Проверка дубликатов портов назначения (по SocketAddr, после разрешения имён)
+            if seen_dest_addrs.contains(&res.tcp) {
+                problems.push(format!("Skipping {}:{} — tcp destination {} already used", spec.upstream, spec.name, res.tcp));
+                continue;
+            }
+            if let Some(udp) = res.udp && seen_dest_addrs.contains(&udp) {
+                problems.push(format!("Skipping {}:{} — udp destination {} already used", spec.upstream, spec.name, udp));
+                continue;
+            }
+            seen_dest_addrs.insert(res.tcp);
+            seen_dest_addrs.extend(res.udp);
+
+            let udp = res.udp.map_or_else(|| "нет".to_string(), |a| a.to_string());
+            match &res.host {
+                Some(host) => println!("Добавлен домен '{}' ({})\n> tcp:{}\n> udp:{}", spec.name, host, res.tcp, udp),
+                None => println!("Добавлен домен '{}'\n> tcp:{}\n> udp:{}", spec.name, res.tcp, udp),
+            }
+            let mut route = Route::new(spec.name.clone(), res.tcp, res.udp);
+            route.host = res.host.clone();
+            route.options = spec.options.clone();
+            routes.push(route);
+        }
+        self.router.replace_routes(routes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use crate::proto::route::RouteOptions;
+    use crate::configure::{self, LoadMode};
+
+    /// Заглушка DNS: ответы задаются в тесте, TTL у всех один
+    struct StubResolver {
+        ips: Mutex<HashMap<String, Result<Vec<IpAddr>, String>>>,
+        srv: Mutex<HashMap<String, Vec<SrvTarget>>>,
+        ttl: Duration,
+    }
+
+    impl StubResolver {
+        fn new(ttl: Duration) -> Arc<Self> {
+            Arc::new(Self { ips: Mutex::default(), srv: Mutex::default(), ttl })
+        }
+
+        fn set_ip(&self, host: &str, answer: Result<&str, &str>) {
+            let answer = answer.map(|ip| vec![ip.parse().unwrap()]).map_err(str::to_string);
+            self.ips.lock().unwrap().insert(host.to_string(), answer);
+        }
+    }
+
+    impl Resolver for Arc<StubResolver> {
+        async fn lookup_ip(&self, host: &str) -> Result<Resolved<Vec<IpAddr>>, String> {
+            let answer = self.ips.lock().unwrap().get(host).cloned();
+            let value = answer.unwrap_or_else(|| Err(format!("{}: NXDOMAIN", host)))?;
+            Ok(Resolved { value, valid_until: Instant::now() + self.ttl })
+        }
+
+        async fn lookup_srv(&self, name: &str) -> Result<Resolved<Vec<SrvTarget>>, String> {
+            let value = self.srv.lock().unwrap().get(name).cloned().unwrap_or_default();
+            Ok(Resolved { value, valid_until: Instant::now() + self.ttl })
+        }
+    }
+
+    fn spec(name: &str, upstream: Upstream, tcp_port: u16, udp_port: Option<u16>) -> RouteSpec {
+        RouteSpec { name: name.to_string(), upstream, tcp_port, udp_port, options: RouteOptions::default() }
+    }
+
+    fn tcp_of(router: &Router, name: &str) -> Option<SocketAddr> {
+        router.lookup_route(name).map(|r| r.tcp)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_after_ttl() {
+        let stub = StubResolver::new(Duration::from_secs(30));
+        stub.set_ip("mc.example.com", Ok("192.0.2.1"));
+        let router = Arc::new(Router::new());
+        let specs = vec![spec("a", Upstream::Host("mc.example.com".to_string()), 25565, Some(24454))];
+        let mut resolver = RouteResolver::new(router.clone(), stub.clone(), specs);
+
+        assert!(resolver.sync().await.is_empty());
+        assert_eq!(tcp_of(&router, "a"), Some("192.0.2.1:25565".parse().unwrap()));
+
+        // До истечения TTL имя не переразрешается
+        stub.set_ip("mc.example.com", Ok("192.0.2.2"));
+        tokio::time::advance(Duration::from_secs(20)).await;
+        resolver.sync().await;
+        assert_eq!(tcp_of(&router, "a"), Some("192.0.2.1:25565".parse().unwrap()));
+
+        tokio::time::advance(Duration::from_secs(15)).await;
+        assert!(resolver.sync().await.is_empty());
+        let route = router.lookup_route("a").unwrap();
+        assert_eq!(route.tcp, "192.0.2.2:25565".parse().unwrap());
+        assert_eq!(route.udp, Some("192.0.2.2:24454".parse().unwrap()));
+        assert_eq!(route.host.as_deref(), Some("mc.example.com"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_last_known_address_on_failure() {
+        let stub = StubResolver::new(Duration::from_secs(30));
+        stub.set_ip("mc.example.com", Ok("192.0.2.1"));
+        let router = Arc::new(Router::new());
+        let specs = vec![spec("a", Upstream::Host("mc.example.com".to_string()), 25565, None)];
+        let mut resolver = RouteResolver::new(router.clone(), stub.clone(), specs);
+        resolver.sync().await;
+
+        stub.set_ip("mc.example.com", Err("SERVFAIL"));
+        tokio::time::advance(Duration::from_secs(60)).await;
+        let problems = resolver.sync().await;
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert_eq!(tcp_of(&router, "a"), Some("192.0.2.1:25565".parse().unwrap()));
+
+        // Повтор после ошибки — через DNS_RETRY_INTERVAL, а не через TTL
+        stub.set_ip("mc.example.com", Ok("192.0.2.3"));
+        tokio::time::advance(DNS_RETRY_INTERVAL).await;
+        assert!(resolver.sync().await.is_empty());
+        assert_eq!(tcp_of(&router, "a"), Some("192.0.2.3:25565".parse().unwrap()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reload_keeps_address_when_dns_fails() {
+        let stub = StubResolver::new(Duration::from_secs(300));
+        stub.set_ip("mc.example.com", Ok("192.0.2.1"));
+        stub.set_ip("old.example.com", Ok("192.0.2.9"));
+        let router = Arc::new(Router::new());
+        let specs = vec![
+            spec("a", Upstream::Host("mc.example.com".to_string()), 25565, None),
+            spec("b", Upstream::Host("old.example.com".to_string()), 25565, None),
+        ];
+        let mut resolver = RouteResolver::new(router.clone(), stub.clone(), specs);
+        resolver.sync().await;
+
+        // Перезагрузка во время сбоя DNS: порт меняется, узел тот же; маршрут b удалён
+        stub.set_ip("mc.example.com", Err("SERVFAIL"));
+        resolver.set_specs(vec![spec("a", Upstream::Host("mc.example.com".to_string()), 25570, Some(24454))]);
+        assert_eq!(resolver.sync().await.len(), 1);
+        let route = router.lookup_route("a").unwrap();
+        assert_eq!(route.tcp, "192.0.2.1:25570".parse().unwrap());
+        assert_eq!(route.udp, Some("192.0.2.1:24454".parse().unwrap()));
+        assert!(router.lookup_route("b").is_none());
+
+        // Переразрешается сразу, не дожидаясь TTL
+        stub.set_ip("mc.example.com", Ok("192.0.2.2"));
+        resolver.set_specs(vec![spec("a", Upstream::Host("mc.example.com".to_string()), 25570, None)]);
+        assert!(resolver.sync().await.is_empty());
+        assert_eq!(tcp_of(&router, "a"), Some("192.0.2.2:25570".parse().unwrap()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reloaded_config_keeps_resolved_upstreams() {
+        let dir = std::env::temp_dir().join(format!("mc-proxy-dns-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("proxy.json");
+        let path = path.to_str().unwrap();
+        let write = |routes: &str| {
+            let config = format!(r#"{{ "version": 2, "listeners": {{ "tcp": "0.0.0.0:25565" }}, "routes": [{}] }}"#, routes);
+            std::fs::write(path, config).unwrap();
+        };
+        let lobby = r#"{ "name": "lobby", "upstream": "mc.example.com", "tcp_port": 25565 }"#;
+
+        let stub = StubResolver::new(Duration::from_secs(300));
+        stub.set_ip("mc.example.com", Ok("192.0.2.1"));
+        let router = Arc::new(Router::new());
+        write(lobby);
+        let specs = configure::load(path, LoadMode::Strict).unwrap().routes;
+        let mut resolver = RouteResolver::new(router.clone(), stub.clone(), specs);
+        assert!(resolver.sync().await.is_empty());
+
+        // Перезагрузка в мягком режиме с ошибочным маршрутом и во время сбоя DNS
+        stub.set_ip("mc.example.com", Err("SERVFAIL"));
+        write(&format!(r#"{}, {{ "name": "bad-name", "upstream": "10.0.0.1", "tcp_port": 1 }}, {{ "name": "survival", "upstream": "10.0.0.2", "tcp_port": 25565 }}"#, lobby));
+        let config = configure::load(path, LoadMode::Lenient).unwrap();
+        assert_eq!(config.problems.len(), 1);
+        resolver.set_specs(config.routes);
+        assert_eq!(resolver.sync().await.len(), 1);
+        assert_eq!(tcp_of(&router, "lobby"), Some("192.0.2.1:25565".parse().unwrap()));
+        assert_eq!(tcp_of(&router, "survival"), Some("10.0.0.2:25565".parse().unwrap()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn picks_srv_by_priority_then_weight() {
+        let stub = StubResolver::new(Duration::from_secs(30));
+        let srv = |priority, weight, port, target: &str| SrvTarget { priority, weight, port, target: target.to_string() };
+        stub.srv.lock().unwrap().insert("_minecraft._tcp.example.com".to_string(), vec![
+            srv(10, 100, 25001, "low.example.com"),
+            srv(5, 10, 25002, "light.example.com"),
+            srv(5, 50, 25003, "heavy.example.com"),
+        ]);
+        for (host, ip) in [("low.example.com", "192.0.2.1"), ("light.example.com", "192.0.2.2"), ("heavy.example.com", "192.0.2.3")] {
+            stub.set_ip(host, Ok(ip));
+        }
+        // Без SRV записей — сам домен с портом из конфига
+        stub.set_ip("plain.example.com", Ok("192.0.2.4"));
+
+        let router = Arc::new(Router::new());
+        let specs = vec![
+            spec("a", Upstream::Srv("_minecraft._tcp.example.com".to_string()), 25565, Some(24454)),
+            spec("b", Upstream::Srv("_minecraft._tcp.plain.example.com".to_string()), 25570, None),
+        ];
+        let mut resolver = RouteResolver::new(router.clone(), stub, specs);
+        assert!(resolver.sync().await.is_empty());
+
+        let route = router.lookup_route("a").unwrap();
+        assert_eq!(route.tcp, "192.0.2.3:25003".parse().unwrap());
+        // UDP порт SRV не задаёт: он из конфига
+        assert_eq!(route.udp, Some("192.0.2.3:24454".parse().unwrap()));
+        assert_eq!(route.host.as_deref(), Some("heavy.example.com"));
+        assert_eq!(tcp_of(&router, "b"), Some("192.0.2.4:25570".parse().unwrap()));
+    }
+}
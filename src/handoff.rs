@@ -0,0 +1,225 @@
+//! Обновление бинаря без простоя: слушающие сокеты передаются новому процессу.
+//!
+//! Старый процесс по SIGUSR2 запускает текущий бинарь с теми же аргументами; TCP и UDP
+//! сокеты наследуются как файловые дескрипторы (их номера — в `MC_PROXY_INHERITED_FDS`),
+//! вместе с управляющим сокетом. По нему преемник получает снимок UDP сессий и сообщает
+//! о готовности, после чего старый процесс перестаёт принимать соединения и дожидается
+//! завершения своих сессий.
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+
+use crate::proto::UdpSessions;
+use crate::systemd::PID_BOUND_VARS;
+
+/// Номера унаследованных дескрипторов: `tcp=3;udp=4,5;control=6`
+pub const ENV_INHERITED_FDS: &str = "MC_PROXY_INHERITED_FDS";
+const READY: &str = "READY";
+/// Сколько ждать снимок сессий от старого процесса
+const STATE_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Сокеты, унаследованные от предыдущего процесса
+pub struct Inherited {
+    pub tcp: Option<TcpListener>,
+    pub udp: Vec<UdpSocket>,
+    control: UnixStream,
+}
+
+impl Inherited {
+    /// Забрать дескрипторы из окружения; None — процесс запущен обычным образом
+    pub fn from_env() -> io::Result<Option<Self>> {
+        match std::env::var(ENV_INHERITED_FDS) {
+            Ok(spec) => Self::from_spec(&spec).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Забрать дескрипторы по описанию из `fd_spec`
+    fn from_spec(spec: &str) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", ENV_INHERITED_FDS, msg));
+
+        let (mut tcp, mut udp, mut control) = (None, Vec::new(), None);
+        for part in spec.split(';').filter(|p| !p.is_empty()) {
+            let (kind, fds) = part.split_once('=').ok_or_else(|| invalid(format!("ожидалось вид=fd, получено '{}'", part)))?;
+            for fd in fds.split(',').filter(|f| !f.is_empty()) {
+                let fd: RawFd = fd.parse().map_err(|_| invalid(format!("некорректный дескриптор '{}'", fd)))?;
+                let fd = take_fd(fd)?;
+                match kind {
+                    "tcp" if tcp.is_none() => tcp = Some(TcpListener::from(fd)),
+                    "udp" => udp.push(UdpSocket::from(fd)),
+                    "control" if control.is_none() => control = Some(UnixStream::from(fd)),
+                    _ => return Err(invalid(format!("неожиданный дескриптор '{}'", part))),
+                }
+            }
+        }
+        let control = control.ok_or_else(|| invalid("нет управляющего сокета".to_string()))?;
+        Ok(Self { tcp, udp, control })
+    }
+
+    /// Прочитать снимок UDP сессий, отправленный старым процессом. Блокирует поток
+    /// до STATE_READ_TIMEOUT: в async коде вызывается через `spawn_blocking`
+    pub fn read_sessions(&self) -> io::Result<UdpSessions> {
+        self.control.set_read_timeout(Some(STATE_READ_TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new(&self.control).read_line(&mut line)?;
+        serde_json::from_str(&line).map_err(io::Error::other)
+    }
+
+    /// Сообщить старому процессу, что сокеты приняты и он может завершаться
+    pub fn notify_ready(mut self) -> io::Result<()> {
+        writeln!(self.control, "{}", READY)
+    }
+}
+
+/// Принять владение унаследованным дескриптором и снова закрыть его для exec
+pub(crate) fn take_fd(fd: RawFd) -> io::Result<OwnedFd> {
+    // SAFETY: F_GETFD/F_SETFD только проверяют и меняют флаги дескриптора
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: дескриптор передан этому процессу родителем и больше никому не принадлежит
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+/// Запущенный преемник, ещё не подтвердивший готовность
+pub struct Successor {
+    child: Child,
+    control: tokio::net::UnixStream,
+}
+
+/// Запустить новый экземпляр бинаря с теми же аргументами и передать ему сокеты и UDP сессии
+pub async fn spawn_successor(tcp: &impl AsRawFd, udp: &[impl AsRawFd], sessions: &UdpSessions) -> io::Result<Successor> {
+    let (control, child_control) = UnixStream::pair()?;
+    let udp_fds: Vec<RawFd> = udp.iter().map(|s| s.as_raw_fd()).collect();
+    let spec = fd_spec(tcp.as_raw_fd(), &udp_fds, child_control.as_raw_fd());
+    let mut inherit = udp_fds;
+    inherit.extend([tcp.as_raw_fd(), child_control.as_raw_fd()]);
+
+    let mut cmd = Command::new(current_exe()?);
+    cmd.args(std::env::args_os().skip(1)).env(ENV_INHERITED_FDS, spec);
+    for var in PID_BOUND_VARS {
+        cmd.env_remove(var);
+    }
+    // SAFETY: между fork и exec вызывается только fcntl (async-signal-safe)
+    unsafe {
+        cmd.pre_exec(move || {
+            for &fd in &inherit {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let child = cmd.spawn()?;
+    drop(child_control);
+
+    control.set_nonblocking(true)?;
+    let mut control = tokio::net::UnixStream::from_std(control)?;
+    control.write_all(&encode_sessions(sessions)?).await?;
+    Ok(Successor { child, control })
+}
+
+/// Значение `MC_PROXY_INHERITED_FDS`
+fn fd_spec(tcp: RawFd, udp: &[RawFd], control: RawFd) -> String {
+    let udp: Vec<String> = udp.iter().map(|fd| fd.to_string()).collect();
+    format!("tcp={};udp={};control={}", tcp, udp.join(","), control)
+}
+
+/// Снимок UDP сессий одной строкой JSON (см. `Inherited::read_sessions`)
+fn encode_sessions(sessions: &UdpSessions) -> io::Result<Vec<u8>> {
+    let mut state = serde_json::to_vec(sessions).map_err(io::Error::other)?;
+    state.push(b'\n');
+    Ok(state)
+}
+
+impl Successor {
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Дождаться готовности преемника. При ошибке или таймауте преемник завершается,
+    /// а старый процесс продолжает работу.
+    pub async fn wait_ready(mut self, timeout: Duration) -> io::Result<()> {
+        let mut line = String::new();
+        let mut reader = tokio::io::BufReader::new(&mut self.control);
+        let result = tokio::select! {
+            res = reader.read_line(&mut line) => match res {
+                Ok(_) if line.trim_end() == READY => Ok(()),
+                Ok(_) => Err(io::Error::other("преемник завершился, не подтвердив готовность")),
+                Err(e) => Err(io::Error::other(format!("преемник завершился, не подтвердив готовность: {}", e))),
+            },
+            _ = tokio::time::sleep(timeout) => Err(io::Error::new(io::ErrorKind::TimedOut, "преемник не подтвердил готовность")),
+        };
+        if result.is_err() {
+            let _ = self.child.kill().await;
+        }
+        result
+    }
+}
+
+/// Путь к бинарю; если файл заменён новой версией, /proc/self/exe оканчивается на " (deleted)"
+fn current_exe() -> io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    match exe.to_str().and_then(|s| s.strip_suffix(" (deleted)")) {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(exe),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::fd::IntoRawFd;
+    use uuid::Uuid;
+
+    #[test]
+    fn sockets_and_sessions_survive_handoff() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let udp = [UdpSocket::bind("127.0.0.1:0").unwrap(), UdpSocket::bind("127.0.0.1:0").unwrap()];
+        let (mut parent, child) = UnixStream::pair().unwrap();
+        let tcp_addr = tcp.local_addr().unwrap();
+        let udp_addrs: Vec<_> = udp.iter().map(|s| s.local_addr().unwrap()).collect();
+
+        // Дескрипторы переходят преемнику: здесь их владельцем становится from_spec
+        let [udp_a, udp_b] = udp;
+        let spec = fd_spec(tcp.into_raw_fd(), &[udp_a.into_raw_fd(), udp_b.into_raw_fd()], child.into_raw_fd());
+        let inherited = Inherited::from_spec(&spec).unwrap();
+        assert_eq!(inherited.tcp.as_ref().unwrap().local_addr().unwrap(), tcp_addr);
+        let inherited_udp: Vec<_> = inherited.udp.iter().map(|s| s.local_addr().unwrap()).collect();
+        assert_eq!(inherited_udp, udp_addrs);
+
+        let sessions = UdpSessions {
+            clients: vec![("192.0.2.1".parse().unwrap(), "10.0.0.1:24454".parse().unwrap())],
+            players: vec![(Uuid::from_u128(7), "[2001:db8::1]:24454".parse().unwrap())],
+        };
+        parent.write_all(&encode_sessions(&sessions).unwrap()).unwrap();
+        let restored = inherited.read_sessions().unwrap();
+        assert_eq!(restored.clients, sessions.clients);
+        assert_eq!(restored.players, sessions.players);
+
+        inherited.notify_ready().unwrap();
+        let mut reply = String::new();
+        parent.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, format!("{}\n", READY));
+    }
+
+    #[test]
+    fn malformed_spec_is_rejected() {
+        // Только описания, отклоняемые до того, как дескриптор будет забран у процесса
+        for spec in ["", "tcp", "tcp=;udp=", "udp=x;control=4"] {
+            let err = Inherited::from_spec(spec).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub mod configure;
+pub mod consts;
+pub mod dns;
+#[cfg(unix)]
+pub mod handoff;
+pub mod proto;
+pub mod supervisor;
+#[cfg(unix)]
+pub mod systemd;
@@ -1,53 +1,554 @@
-use tokio::net::{TcpListener, UdpSocket};
-use std::{sync::Arc, io::Result};
-use crate::proto::{Router, TcpProxy, UdpProxy};
-use crate::configure::load_and_sync;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::{interval, sleep, Duration};
+use std::{sync::Arc, sync::atomic::Ordering, io::Result, net::SocketAddr};
+use mc_proxy::proto::{Acceptor, Frontend, Router, TcpProxy, UdpProxy};
+use mc_proxy::proto::udp_flow::UdpLimits;
+use mc_proxy::configure::{self, ConfigFormat, Listeners, LoadMode, LoadedConfig, RouteSpec, SessionLimits, ShutdownConfig};
+use mc_proxy::dns::{RouteResolver, SystemResolver};
+use mc_proxy::supervisor::Supervisor;
+use mc_proxy::consts::STATUS_REPORT_INTERVAL;
+#[cfg(unix)]
+use mc_proxy::{consts::HANDOFF_READY_TIMEOUT, handoff, systemd::{self, Notifier}};
 
-mod configure;
-mod consts;
-mod proto;
-
-const CONFIG_PATH: &str = "./proxy.json";
+mod cli;
+use cli::{Cli, Command};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match cli.command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Run => run(cli.config, cli.mode, cli.reload_mode).await,
+        Command::Check => check(&cli.config).await,
+        Command::PrintConfig(format) => print_config(&cli.config, format),
+    }
+}
+
+/// Загрузить конфиг или завершить процесс с ошибкой
+fn load_config(path: &str, mode: LoadMode) -> LoadedConfig {
+    match configure::load(path, mode) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Couldn't load config: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn system_resolver() -> SystemResolver {
+    match SystemResolver::new() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("DNS: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Проверить конфиг, включая разрешение имён и дубликаты адресов после него
+async fn check(path: &str) -> Result<()> {
+    let config = load_config(path, LoadMode::Lenient);
+    println!("{}", config.summary());
+    let mut problems = config.problems;
+
     let router = Arc::new(Router::new());
+    let mut routes = RouteResolver::new(router.clone(), system_resolver(), config.routes);
+    problems.extend(routes.sync().await);
 
-    // Загружаем конфиг и получаем порты
-    let tcp_port: u16 = match load_and_sync(&router, CONFIG_PATH) {
-        Ok(p) => p,
+    if problems.is_empty() {
+        println!("\x1b[1;32mКонфиг {} корректен: маршрутов {}\x1b[0m", path, router.route_count());
+        return Ok(());
+    }
+    eprintln!("\x1b[1;31mКонфиг {}: найдено проблем {}\x1b[0m", path, problems.len());
+    for problem in &problems {
+        eprintln!("  - {}", problem);
+    }
+    std::process::exit(1);
+}
+
+/// Вывести итоговый конфиг (по умолчанию в формате исходного файла)
+fn print_config(path: &str, format: Option<ConfigFormat>) -> Result<()> {
+    let config = load_config(path, LoadMode::Lenient);
+    for problem in &config.problems {
+        eprintln!("\x1b[33m{}\x1b[0m", problem);
+    }
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(path));
+    match format.render(&configure::effective_document(&config)) {
+        Ok(text) => println!("{}", text.trim_end()),
         Err(e) => {
-            eprintln!("Couldn't get the port: {}", e);
+            eprintln!("Не удалось вывести конфиг: {}", e);
             std::process::exit(1);
         }
+    }
+    Ok(())
+}
+
+/// Вывести пропущенные записи и сводку загрузки
+fn report_loaded(config: &LoadedConfig) {
+    for problem in &config.problems {
+        eprintln!("\x1b[33m{}\x1b[0m", problem);
+    }
+    println!("{}", config.summary());
+}
+
+/// Перезагрузка конфига по SIGHUP. Если конфиг не загрузился, остаётся прежний.
+/// Адреса listeners, параметры shutdown и limits применяются только при перезапуске.
+#[cfg(unix)]
+async fn reload_on_sighup(path: String, mode: LoadMode, current: (Listeners, ShutdownConfig, SessionLimits), routes: mpsc::Sender<Vec<RouteSpec>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("SIGHUP недоступен, перезагрузка конфига отключена: {}", e);
+            return;
+        }
     };
+    let notifier = notifier();
+    while hangup.recv().await.is_some() {
+        println!("\x1b[1;32mПерезагрузка конфига {}\x1b[0m", path);
+        if let Some(n) = &notifier {
+            notify(n.reloading("Перезагрузка конфига"));
+        }
+        let config = match configure::load(&path, mode) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("\x1b[31mПерезагрузка отменена, действует прежний конфиг: {}\x1b[0m", e);
+                if let Some(n) = &notifier {
+                    notify(n.ready("Перезагрузка отменена, действует прежний конфиг"));
+                }
+                continue;
+            }
+        };
+        report_loaded(&config);
+        if config.listeners != current.0 || config.shutdown != current.1 || config.limits != current.2 {
+            eprintln!("\x1b[33mlisteners/shutdown/limits изменены — применятся после перезапуска или обновления (SIGUSR2)\x1b[0m");
+        }
+        let count = config.routes.len();
+        if routes.send(config.routes).await.is_err() {
+            return;
+        }
+        if let Some(n) = &notifier {
+            notify(n.ready(&format!("Конфиг перезагружен, маршрутов {}", count)));
+        }
+    }
+}
+
+async fn run(path: String, mode: LoadMode, reload_mode: LoadMode) -> Result<()> {
+    let router = Arc::new(Router::new());
+
+    #[cfg(unix)]
+    match mc_proxy::proto::acceptor::raise_fd_limit() {
+        Ok((old, new)) if new > old => println!("Лимит дескрипторов поднят: {} -> {}", old, new),
+        Ok(_) => {}
+        Err(e) => eprintln!("\x1b[33mНе удалось поднять лимит дескрипторов: {}\x1b[0m", e),
+    }
 
-    println!("\x1b[1;32m{}\x1b[0m", "Запуск прокси");
+    // Загружаем конфиг и получаем порты
+    println!("\x1b[1;32mВалидация конфига\x1b[0m");
+    let config = load_config(&path, mode);
+    let listeners = config.listeners.clone();
+    report_loaded(&config);
+    // Адрес игрока от фронтового сервиса; ключ проверен при загрузке конфига
+    let frontend = match &listeners.frontend {
+        Some(f) => Some(Arc::new(Frontend::new(f).map_err(std::io::Error::other)?)),
+        None => None,
+    };
 
-    let udp_socket = UdpSocket::bind(format!("0.0.0.0:{}", 24454)).await?;
-    println!("UDP proxy listening on 0.0.0.0:{}", 24454);
-    
-    // Запускаем UDP прокси в фоне, передав владение сокетом в задачу
-    let udp_router = router.clone();
-    tokio::spawn(async move {
-        let mut proxy = UdpProxy::new(udp_socket, udp_router);
-        if let Err(e) = proxy.run().await {
-            eprintln!("UDP proxy error: {}", e);
+    // При обновлении бинаря (SIGUSR2) слушающие сокеты и UDP сессии приходят от прежнего процесса,
+    // при активации через mc-proxy.socket — от systemd
+    #[cfg(unix)]
+    let mut inherited = inherit_sockets(&router).await;
+    #[cfg(unix)]
+    let (inherited_tcp, inherited_udp) = match &mut inherited {
+        Some(i) => (i.tcp.take(), std::mem::take(&mut i.udp)),
+        None => activated_sockets(),
+    };
+    #[cfg(not(unix))]
+    let (inherited_tcp, inherited_udp): (Option<std::net::TcpListener>, Vec<std::net::UdpSocket>) = (None, Vec::new());
+
+    // Разрешаем upstream маршрутов; имена хостов переразрешаются в фоне по TTL,
+    // новые маршруты приходят из перезагрузки конфига
+    let mut routes = RouteResolver::new(router.clone(), system_resolver(), config.routes);
+    for problem in routes.sync().await {
+        eprintln!("\x1b[33m{}\x1b[0m", problem);
+    }
+    let (reload_tx, reload_rx) = mpsc::channel(1);
+    tokio::spawn(routes.run(reload_rx));
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(path, reload_mode, (listeners.clone(), config.shutdown.clone(), config.limits.clone()), reload_tx));
+    #[cfg(not(unix))]
+    let _ = (reload_mode, reload_tx);
+
+    println!("\x1b[1;32mЗапуск прокси\x1b[0m");
+
+    // Остановка: сигнал рассылается UDP прокси через watch
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let supervisor = Supervisor::new(stop_rx.clone());
+
+    // UDP прокси: по воркеру на ядро, все слушают один порт через SO_REUSEPORT
+    let mut udp_sockets = Vec::new();
+    let udp_task = match listeners.udp {
+        Some(udp_addr) => {
+            let udp_proxy = if inherited_udp.is_empty() {
+                let udp_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+                UdpProxy::bind(udp_addr, udp_workers, router.clone(), UdpLimits::default())?
+            } else {
+                let sockets = inherited_udp.into_iter()
+                    .map(|s| s.set_nonblocking(true).and_then(|_| UdpSocket::from_std(s)))
+                    .collect::<Result<Vec<_>>>()?;
+                UdpProxy::new(sockets, router.clone(), UdpLimits::default())
+            };
+            let local_addr = udp_proxy.local_addr()?;
+            if local_addr != udp_addr && udp_addr.port() != 0 {
+                println!("\x1b[33mlisteners.udp {} не применён: используется переданный сокет {}\x1b[0m", udp_addr, local_addr);
+            }
+            udp_sockets = udp_proxy.sockets().to_vec();
+            println!("UDP proxy listening on {} ({} воркеров)", local_addr, udp_sockets.len());
+
+            // Запускаем UDP прокси в фоне; после сбоя он перезапускается на тех же сокетах
+            let udp_proxy = Arc::new(udp_proxy);
+            let stop_rx = stop_rx.clone();
+            Some(supervisor.spawn("UDP proxy", move || {
+                let (udp_proxy, mut stop_rx) = (udp_proxy.clone(), stop_rx.clone());
+                async move {
+                    let stopped = async move {
+                        let _ = stop_rx.wait_for(|stop| *stop).await;
+                    };
+                    udp_proxy.run_until(stopped).await
+                }
+            }))
+        }
+        None => {
+            println!("UDP proxy отключён (listeners.udp не задан)");
+            None
         }
+    };
+
+    let listener = match inherited_tcp {
+        Some(listener) => {
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+            if listener.local_addr()? != listeners.tcp && listeners.tcp.port() != 0 {
+                println!("\x1b[33mlisteners.tcp {} не применён: используется переданный сокет {}\x1b[0m", listeners.tcp, listener.local_addr()?);
+            }
+            listener
+        }
+        None => TcpListener::bind(listeners.tcp).await?,
+    };
+    println!("TCP proxy listening on {}", listener.local_addr()?);
+    let limits = &config.limits;
+    match limits.max_sessions {
+        Some(max) => println!("Лимит сессий: {} (сверх лимита: {})", max, limits.when_full),
+        None => println!("Лимит сессий: не задан"),
+    }
+    let acceptor = Acceptor::new(listener, limits.max_sessions, limits.when_full);
+
+    // systemd узнаёт о готовности (и о новом MAINPID) до того, как прежний процесс начнёт завершаться
+    let notifier = notifier();
+    if let Some(n) = &notifier {
+        notify(n.ready(&status_line(&router, 0, &acceptor, &supervisor)));
+    }
+    #[cfg(unix)]
+    if let Some(inherited) = inherited
+        && let Err(e) = inherited.notify_ready()
+    {
+        eprintln!("\x1b[33mНе удалось сообщить о готовности прежнему процессу: {}\x1b[0m", e);
+    }
+
+    let mut sessions = JoinSet::new();
+    let upgraded = {
+        let stop = shutdown_signal();
+        let upgrade = upgrade_on_sigusr2(acceptor.listener(), &udp_sockets, &router);
+        tokio::pin!(stop, upgrade);
+        // Тики из цикла приёма: watchdog срабатывает, если runtime или цикл зависли
+        let period = notifier.as_ref().and_then(|n| n.watchdog()).map_or(STATUS_REPORT_INTERVAL, |w| (w / 2).min(STATUS_REPORT_INTERVAL));
+        let mut heartbeat = interval(period);
+        let mut healthy = true;
+        loop {
+            tokio::select! {
+                (inbound, peer, permit) = acceptor.accept() => {
+                    if let Err(e) = listeners.socket.apply_stream(&inbound) {
+                        eprintln!("{}: параметры сокета не применены: {}", peer, e);
+                    }
+                    let router = router.clone();
+                    let frontend = frontend.clone();
+                    sessions.spawn(async move {
+                        // Место в лимите сессий освобождается вместе с сессией
+                        let _permit = permit;
+                        if let Err(e) = TcpProxy::new(inbound, router).with_frontend(frontend).run().await {
+                            eprintln!("{} соединение разорвано: {}", peer, e);
+                        }
+                    });
+                }
+                // Завершённые сессии убираются из набора
+                Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+                _ = heartbeat.tick() => {
+                    if let Some(report) = acceptor.report() {
+                        eprintln!("\x1b[33m{}\x1b[0m", report);
+                    }
+                    // Неисправная подсистема видна в логе и статусе; watchdog подтверждается,
+                    // пока жив цикл приёма
+                    if supervisor.healthy() != healthy {
+                        healthy = !healthy;
+                        if healthy {
+                            println!("\x1b[1;32mПрокси снова исправен\x1b[0m");
+                        } else {
+                            eprintln!("\x1b[31mПрокси неисправен: {}\x1b[0m", supervisor.problems().unwrap_or_default());
+                        }
+                    }
+                    if let Some(n) = &notifier {
+                        notify(n.heartbeat(&status_line(&router, sessions.len(), &acceptor, &supervisor)));
+                    }
+                }
+                _ = &mut stop => break false,
+                _ = &mut upgrade => break true,
+            }
+        }
+    };
+
+    if upgraded {
+        // Новые соединения и UDP уже обслуживает новый процесс: здесь только
+        // дожидаемся своих TCP сессий
+        let _ = stop_tx.send(true);
+        if let Some(task) = udp_task {
+            let _ = task.await;
+        }
+        drop((acceptor, udp_sockets));
+        drain(None, sessions, router, &config.shutdown).await;
+        println!("\x1b[1;32mПрокси остановлен, работу продолжает новый процесс\x1b[0m");
+        return Ok(());
+    }
+
+    if let Some(n) = &notifier {
+        notify(n.stopping(&format!("Остановка: ожидание {} сессий", sessions.len())));
+    }
+    drain(Some(acceptor.into_listener()), sessions, router, &config.shutdown).await;
+
+    // UDP нужен игрокам до конца TCP сессий, поэтому останавливается последним
+    let _ = stop_tx.send(true);
+    if let Some(task) = udp_task {
+        let _ = task.await;
+    }
+    println!("\x1b[1;32mПрокси остановлен\x1b[0m");
+    Ok(())
+}
+
+/// Сокеты активации systemd (пустой набор, если процесс запущен не через .socket юнит)
+#[cfg(unix)]
+fn activated_sockets() -> (Option<std::net::TcpListener>, Vec<std::net::UdpSocket>) {
+    match systemd::activated_sockets() {
+        Ok(Some(sockets)) => {
+            println!(
+                "Сокеты получены от systemd: tcp {}, udp {}",
+                if sockets.tcp.is_some() { "да" } else { "нет" },
+                sockets.udp.len(),
+            );
+            (sockets.tcp, sockets.udp)
+        }
+        Ok(None) => (None, Vec::new()),
+        Err(e) => {
+            eprintln!("Couldn't take systemd sockets: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn notifier() -> Option<Notifier> {
+    systemd::Notifier::from_env().unwrap_or_else(|e| {
+        eprintln!("\x1b[33mNOTIFY_SOCKET не используется: {}\x1b[0m", e);
+        None
+    })
+}
+
+/// Уведомления systemd есть только на unix
+#[cfg(not(unix))]
+struct Notifier;
+
+#[cfg(not(unix))]
+impl Notifier {
+    fn watchdog(&self) -> Option<Duration> { None }
+    fn ready(&self, _: &str) -> Result<()> { Ok(()) }
+    fn heartbeat(&self, _: &str) -> Result<()> { Ok(()) }
+    fn stopping(&self, _: &str) -> Result<()> { Ok(()) }
+}
+
+#[cfg(not(unix))]
+fn notifier() -> Option<Notifier> {
+    None
+}
+
+/// Ошибка отправки уведомления не мешает работе прокси
+fn notify(result: Result<()>) {
+    if let Err(e) = result {
+        eprintln!("\x1b[33msd_notify: {}\x1b[0m", e);
+    }
+}
+
+fn status_line(router: &Router, sessions: usize, acceptor: &Acceptor, supervisor: &Supervisor) -> String {
+    let status = format!(
+        "маршрутов {}, активных сессий {}, отклонено по лимиту {}",
+        router.route_count(),
+        sessions,
+        acceptor.stats().rejected.load(Ordering::Relaxed),
+    );
+    match supervisor.problems() {
+        Some(problems) => format!("{}; {}", status, problems),
+        None => status,
+    }
+}
+
+/// Забрать сокеты, переданные прежним процессом, и восстановить его UDP сессии
+#[cfg(unix)]
+async fn inherit_sockets(router: &Router) -> Option<handoff::Inherited> {
+    let inherited = match handoff::Inherited::from_env() {
+        Ok(inherited) => inherited?,
+        Err(e) => {
+            eprintln!("Couldn't take inherited sockets: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // Снимок читается с таймаутом блокирующим вызовом: не занимаем им поток runtime
+    let read = tokio::task::spawn_blocking(move || {
+        let sessions = inherited.read_sessions();
+        (inherited, sessions)
     });
+    let (inherited, sessions) = match read.await {
+        Ok(read) => read,
+        Err(e) => {
+            eprintln!("Couldn't take inherited sockets: {}", e);
+            std::process::exit(1);
+        }
+    };
+    match sessions {
+        Ok(sessions) => println!(
+            "Сокеты получены от прежнего процесса, восстановлено UDP сессий: {}",
+            router.restore_udp_sessions(sessions),
+        ),
+        Err(e) => eprintln!("\x1b[33mНе удалось получить UDP сессии прежнего процесса: {}\x1b[0m", e),
+    }
+    Some(inherited)
+}
 
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", tcp_port)).await?;
-    println!("TCP proxy listening on 0.0.0.0:{}", tcp_port);
+/// Обновление бинаря по SIGUSR2: запустить новый процесс и передать ему сокеты.
+/// Завершается, когда новый процесс подтвердил готовность; при неудаче работа продолжается.
+#[cfg(unix)]
+async fn upgrade_on_sigusr2(listener: &TcpListener, udp: &[Arc<UdpSocket>], router: &Router) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let Ok(mut usr2) = signal(SignalKind::user_defined2()) else {
+        return std::future::pending().await;
+    };
+    loop {
+        usr2.recv().await;
+        println!("\x1b[1;32mОбновление: запуск нового процесса\x1b[0m");
+        let started = async {
+            let successor = handoff::spawn_successor(listener, udp, &router.udp_sessions()).await?;
+            let pid = successor.id().unwrap_or_default();
+            successor.wait_ready(HANDOFF_READY_TIMEOUT).await.map(|_| pid)
+        };
+        match started.await {
+            Ok(pid) => {
+                println!("\x1b[1;32mНовый процесс (pid {}) принял сокеты\x1b[0m", pid);
+                return;
+            }
+            Err(e) => eprintln!("\x1b[31mОбновление не удалось, работа продолжается: {}\x1b[0m", e),
+        }
+    }
+}
 
+#[cfg(not(unix))]
+async fn upgrade_on_sigusr2(_: &TcpListener, _: &[Arc<UdpSocket>], _: &Router) {
+    std::future::pending().await
+}
+
+/// Дождаться SIGTERM или SIGINT
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Принять соединение, если listener есть; иначе ждать бесконечно
+async fn accept_if(listener: &Option<TcpListener>) -> Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Остановка TCP: новые соединения не проксируются (с disconnect_message им отвечают
+/// этим сообщением), активные сессии завершаются сами до drain_timeout, затем закрываются.
+/// Повторный сигнал закрывает сессии сразу. listener — None, если сокет передан новому процессу.
+async fn drain(listener: Option<TcpListener>, mut sessions: JoinSet<()>, router: Arc<Router>, shutdown: &ShutdownConfig) {
+    println!(
+        "\x1b[1;32mОстановка: приём соединений прекращён, ожидание {} сессий до {} с\x1b[0m",
+        sessions.len(),
+        shutdown.drain_timeout_secs,
+    );
+    let message = shutdown.disconnect_message.clone();
+    let listener = listener.filter(|_| message.is_some());
+    let mut refused = JoinSet::new();
+
+    let deadline = sleep(Duration::from_secs(shutdown.drain_timeout_secs));
+    let stop = shutdown_signal();
+    tokio::pin!(deadline, stop);
     loop {
-        let (inbound, peer) = listener.accept().await?;
-        let router = router.clone();
-        tokio::spawn(async move {
-            if let Err(e) = crate::TcpProxy::new(inbound, router).run().await {
-                eprintln!("{} соединение разорвано: {}", peer, e);
+        tokio::select! {
+            res = sessions.join_next() => {
+                if res.is_none() {
+                    println!("Все сессии завершены");
+                    break;
+                }
             }
-        });
+            Ok((inbound, peer)) = accept_if(&listener) => {
+                let message = message.clone().unwrap_or_default();
+                let router = router.clone();
+                refused.spawn(async move {
+                    if let Err(e) = TcpProxy::new(inbound, router).refuse(&message).await {
+                        eprintln!("{} соединение разорвано: {}", peer, e);
+                    }
+                });
+            }
+            Some(_) = refused.join_next(), if !refused.is_empty() => {}
+            _ = &mut deadline => {
+                println!("\x1b[33mВремя ожидания истекло, закрыто сессий: {}\x1b[0m", sessions.len());
+                break;
+            }
+            _ = &mut stop => {
+                println!("\x1b[33mПовторный сигнал, закрыто сессий: {}\x1b[0m", sessions.len());
+                break;
+            }
+        }
     }
+    sessions.shutdown().await;
+    refused.shutdown().await;
 }
 
 // 172.31.123.61
\ No newline at end of file
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, io};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep, Duration};
+
+use crate::consts::{ACCEPT_BACKOFF_MAX, ACCEPT_BACKOFF_MIN};
+
+/// Что делать с подключениями сверх лимита сессий
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Принять и сразу закрыть: очередь ядра не забивается при наплыве подключений
+    #[default]
+    Reject,
+    /// Не принимать, пока не освободится место: подключения ждут в очереди ядра
+    Pause,
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Reject => "reject",
+            Self::Pause => "pause",
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct AcceptStats {
+    pub accepted: AtomicU64,
+    /// Закрыто сразу после accept из-за лимита сессий
+    pub rejected: AtomicU64,
+    pub errors: AtomicU64,
+}
+
+/// Приём TCP подключений с общим лимитом одновременных сессий. Ошибки accept не
+/// прерывают работу: при нехватке дескрипторов или памяти приём приостанавливается
+/// с нарастающей задержкой.
+pub struct Acceptor {
+    listener: TcpListener,
+    permits: Option<Arc<Semaphore>>,
+    max_sessions: Option<usize>,
+    policy: OverflowPolicy,
+    stats: AcceptStats,
+    /// (rejected, errors) на момент последнего отчёта
+    reported: (AtomicU64, AtomicU64),
+    /// Текущая пауза после нехватки ресурсов, мс (0 — accept успешен). Хранится здесь,
+    /// а не в `accept`: его future пересоздаётся в каждой итерации select
+    backoff_ms: AtomicU64,
+}
+
+impl Acceptor {
+    pub fn new(listener: TcpListener, max_sessions: Option<usize>, policy: OverflowPolicy) -> Self {
+        Self {
+            listener,
+            permits: max_sessions.map(|n| Arc::new(Semaphore::new(n))),
+            max_sessions,
+            policy,
+            stats: AcceptStats::default(),
+            reported: (AtomicU64::new(0), AtomicU64::new(0)),
+            backoff_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn listener(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    pub fn into_listener(self) -> TcpListener {
+        self.listener
+    }
+
+    pub fn stats(&self) -> &AcceptStats {
+        &self.stats
+    }
+
+    /// Принять следующее подключение. Разрешение лимита (если лимит задан) нужно
+    /// держать, пока жива сессия.
+    pub async fn accept(&self) -> (TcpStream, SocketAddr, Option<OwnedSemaphorePermit>) {
+        loop {
+            let reserved = match (&self.permits, self.policy) {
+                (Some(permits), OverflowPolicy::Pause) => permits.clone().acquire_owned().await.ok(),
+                _ => None,
+            };
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    let permit = match (reserved, &self.permits) {
+                        (Some(permit), _) => Some(permit),
+                        (None, Some(permits)) => match permits.clone().try_acquire_owned() {
+                            Ok(permit) => Some(permit),
+                            Err(_) => {
+                                self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+                                continue;
+                            }
+                        },
+                        (None, None) => None,
+                    };
+                    self.stats.accepted.fetch_add(1, Ordering::Relaxed);
+                    self.backoff_ms.store(0, Ordering::Relaxed);
+                    return (stream, peer, permit);
+                }
+                Err(e) if is_resource_exhausted(&e) => {
+                    self.stats.errors.fetch_add(1, Ordering::Relaxed);
+                    let prev = Duration::from_millis(self.backoff_ms.load(Ordering::Relaxed));
+                    let backoff = (prev * 2).clamp(ACCEPT_BACKOFF_MIN, ACCEPT_BACKOFF_MAX);
+                    self.backoff_ms.store(backoff.as_millis() as u64, Ordering::Relaxed);
+                    eprintln!("\x1b[31maccept: {} ({}); пауза {} мс\x1b[0m", e, fd_usage_line(), backoff.as_millis());
+                    sleep(backoff).await;
+                }
+                // Клиент сбросил подключение до accept и т.п. — к следующему
+                Err(e) => {
+                    self.stats.errors.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("accept: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Строка отчёта, если с прошлого раза были отказы или ошибки
+    pub fn report(&self) -> Option<String> {
+        let rejected = self.stats.rejected.load(Ordering::Relaxed);
+        let errors = self.stats.errors.load(Ordering::Relaxed);
+        let prev_rejected = self.reported.0.swap(rejected, Ordering::Relaxed);
+        let prev_errors = self.reported.1.swap(errors, Ordering::Relaxed);
+        if rejected == prev_rejected && errors == prev_errors {
+            return None;
+        }
+        let limit = self.max_sessions.map_or_else(|| "нет".to_string(), |n| n.to_string());
+        Some(format!(
+            "Приём: отклонено по лимиту сессий {} (+{}, лимит {}), ошибок accept {} (+{}); {}",
+            rejected,
+            rejected - prev_rejected,
+            limit,
+            errors,
+            errors - prev_errors,
+            fd_usage_line(),
+        ))
+    }
+}
+
+/// Ошибки, после которых accept имеет смысл повторить только с задержкой
+fn is_resource_exhausted(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if let Some(code) = e.raw_os_error() {
+        return matches!(code, libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM);
+    }
+    e.kind() == io::ErrorKind::OutOfMemory
+}
+
+/// Открытые дескрипторы процесса (None, если не посчитать: не Linux или сами дескрипторы
+/// кончились) и мягкий лимит RLIMIT_NOFILE
+#[cfg(unix)]
+pub fn fd_usage() -> (Option<usize>, Option<libc::rlim_t>) {
+    // Сам каталог тоже открыт на время чтения
+    let open = std::fs::read_dir("/proc/self/fd").ok().map(|dir| dir.count().saturating_sub(1));
+    (open, nofile_limit().map(|(soft, _)| soft))
+}
+
+#[cfg(unix)]
+fn fd_usage_line() -> String {
+    let (open, limit) = fd_usage();
+    let show = |v: Option<String>| v.unwrap_or_else(|| "?".to_string());
+    format!("дескрипторов {}/{}", show(open.map(|n| n.to_string())), show(limit.map(|n| n.to_string())))
+}
+
+#[cfg(not(unix))]
+fn fd_usage_line() -> String {
+    "дескрипторов ?".to_string()
+}
+
+/// (мягкий, жёсткий) RLIMIT_NOFILE
+#[cfg(unix)]
+fn nofile_limit() -> Option<(libc::rlim_t, libc::rlim_t)> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit только заполняет переданную структуру
+    let res = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    (res == 0).then_some((limit.rlim_cur, limit.rlim_max))
+}
+
+/// Поднять мягкий лимит дескрипторов до жёсткого: каждая сессия занимает два дескриптора,
+/// каждый UDP поток — ещё один. Возвращает (прежний, новый) мягкий лимит.
+#[cfg(unix)]
+pub fn raise_fd_limit() -> io::Result<(libc::rlim_t, libc::rlim_t)> {
+    let (soft, hard) = nofile_limit().ok_or_else(io::Error::last_os_error)?;
+    if soft >= hard {
+        return Ok((soft, soft));
+    }
+    let limit = libc::rlimit { rlim_cur: hard, rlim_max: hard };
+    // SAFETY: setrlimit только читает переданную структуру
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((soft, hard))
+}
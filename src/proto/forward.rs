@@ -0,0 +1,79 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout, Duration, Instant};
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::proto::RateLimiter;
+use crate::consts::FORWARD_BUF_SIZE;
+
+/// Время последней активности сессии, общее для обоих направлений:
+/// сессия считается простаивающей, только если трафика нет ни в одну сторону.
+pub struct Activity {
+    epoch: Instant,
+    last_ms: AtomicU64,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Self { epoch: Instant::now(), last_ms: AtomicU64::new(0) }
+    }
+
+    pub fn touch(&self) {
+        self.last_ms.store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.epoch.elapsed().saturating_sub(last)
+    }
+}
+
+/// Копировать reader -> writer до EOF с необязательным ограничением скорости и таймаутом простоя.
+/// Возвращает число переданных байт.
+pub async fn forward<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    mut limiter: Option<RateLimiter>,
+    idle: Option<Duration>,
+    activity: &Activity,
+) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; FORWARD_BUF_SIZE];
+    let mut total = 0u64;
+    loop {
+        // Читаем не больше, чем лимитер способен выдать за раз
+        let max = limiter.as_ref().map_or(buf.len(), |l| l.capacity().clamp(1, buf.len()));
+        let n = match idle {
+            Some(idle) => loop {
+                let remaining = idle.saturating_sub(activity.idle());
+                if remaining.is_zero() {
+                    return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Превышено время простоя сессии"));
+                }
+                if let Ok(res) = timeout(remaining, reader.read(&mut buf[..max])).await {
+                    break res?;
+                }
+            },
+            None => reader.read(&mut buf[..max]).await?,
+        };
+        if n == 0 {
+            break;
+        }
+        activity.touch();
+        if let Some(limiter) = limiter.as_mut() {
+            limiter.acquire(n).await;
+        }
+        writer.write_all(&buf[..n]).await?;
+        total += n as u64;
+    }
+    writer.shutdown().await?;
+    Ok(total)
+}
@@ -0,0 +1,320 @@
+//! Фронтовые сервисы защиты от DDoS (TCPShield и т.п.) подключаются к прокси сами,
+//! а настоящий адрес игрока передают в адресе сервера Handshake вместе с подписью.
+//! Схема разбора подключается реализацией `HostnameScheme`; данные принимаются, только
+//! если подпись сходится с открытым ключом из конфига.
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::consts::FRONTEND_MAX_AGE;
+
+/// Формат адреса сервера, в котором фронт передаёт адрес игрока
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontendScheme {
+    /// `host///ip:port///timestamp///signature`, подписана часть до последнего `///`
+    Tcpshield,
+}
+
+/// Алгоритм подписи фронта
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignatureAlgorithm {
+    EcdsaP256Sha256,
+    EcdsaP384Sha384,
+    RsaPkcs1Sha256,
+    RsaPkcs1Sha512,
+    Ed25519,
+}
+
+impl SignatureAlgorithm {
+    fn verification(self) -> &'static dyn VerificationAlgorithm {
+        match self {
+            Self::EcdsaP256Sha256 => &signature::ECDSA_P256_SHA256_ASN1,
+            Self::EcdsaP384Sha384 => &signature::ECDSA_P384_SHA384_ASN1,
+            Self::RsaPkcs1Sha256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+            Self::RsaPkcs1Sha512 => &signature::RSA_PKCS1_2048_8192_SHA512,
+            Self::Ed25519 => &signature::ED25519,
+        }
+    }
+}
+
+/// `listeners.frontend`: подключения приходят через фронтовой сервис
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrontendConfig {
+    pub scheme: FrontendScheme,
+    pub algorithm: SignatureAlgorithm,
+    /// Открытый ключ фронта: PEM (`-----BEGIN PUBLIC KEY-----`) или base64 от DER SubjectPublicKeyInfo
+    pub public_key: String,
+    /// Насколько метка времени в адресе может расходиться с часами прокси
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Отклонять подключения без данных фронта (только через фронт)
+    #[serde(default)]
+    pub require: bool,
+}
+
+/// Результат разбора адреса сервера
+#[derive(Clone, Debug)]
+pub struct Decoded {
+    /// Адрес сервера, к которому подключался игрок (с метками FML, если они были)
+    pub host: String,
+    /// Настоящий адрес игрока
+    pub client: SocketAddr,
+}
+
+/// Схема разбора адреса сервера из Handshake
+pub trait HostnameScheme: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// None — адрес не в формате схемы; ошибка — формат тот, но данным нельзя доверять
+    fn decode(&self, address: &str, now: SystemTime) -> Result<Option<Decoded>, String>;
+}
+
+pub struct Frontend {
+    scheme: Box<dyn HostnameScheme>,
+    require: bool,
+}
+
+impl Frontend {
+    pub fn new(config: &FrontendConfig) -> Result<Self, String> {
+        let key = parse_public_key(&config.public_key)?;
+        let max_age = config.max_age_secs.map_or(FRONTEND_MAX_AGE, Duration::from_secs);
+        let scheme: Box<dyn HostnameScheme> = match config.scheme {
+            FrontendScheme::Tcpshield => Box::new(TcpShield {
+                key: UnparsedPublicKey::new(config.algorithm.verification(), key),
+                max_age,
+            }),
+        };
+        Ok(Self { scheme, require: config.require })
+    }
+
+    /// Разобрать адрес сервера; None — обычный адрес (если прямые подключения разрешены)
+    pub fn decode(&self, address: &str) -> Result<Option<Decoded>, String> {
+        match self.scheme.decode(address, SystemTime::now())? {
+            Some(decoded) => Ok(Some(decoded)),
+            None if self.require => Err(format!("подключение не через {}: прямые подключения запрещены", self.scheme.name())),
+            None => Ok(None),
+        }
+    }
+}
+
+struct TcpShield {
+    key: UnparsedPublicKey<Vec<u8>>,
+    max_age: Duration,
+}
+
+impl HostnameScheme for TcpShield {
+    fn name(&self) -> &'static str {
+        "tcpshield"
+    }
+
+    fn decode(&self, address: &str, now: SystemTime) -> Result<Option<Decoded>, String> {
+        // Метки FML клиент Forge дописывает после всего адреса
+        let (encoded, markers) = address.find('\0').map_or((address, ""), |i| address.split_at(i));
+        let parts: Vec<&str> = encoded.splitn(4, "///").collect();
+        let [host, client, timestamp, sig] = parts[..] else {
+            return Ok(None);
+        };
+
+        // Пока подпись не проверена, остальным полям верить нельзя
+        let signed = &encoded[..encoded.len() - sig.len() - "///".len()];
+        let sig = BASE64.decode(sig).map_err(|_| "tcpshield: подпись не в base64".to_string())?;
+        self.key.verify(signed.as_bytes(), &sig).map_err(|_| "tcpshield: неверная подпись".to_string())?;
+
+        let timestamp: u64 = timestamp.parse().map_err(|_| format!("tcpshield: некорректная метка времени '{}'", timestamp))?;
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if now.abs_diff(timestamp) > self.max_age.as_secs() {
+            return Err(format!("tcpshield: метка времени расходится с часами на {} с", now.abs_diff(timestamp)));
+        }
+
+        let client = parse_client(client).ok_or_else(|| format!("tcpshield: некорректный адрес клиента '{}'", client))?;
+        Ok(Some(Decoded { host: format!("{}{}", host, markers), client }))
+    }
+}
+
+/// `ip:port`, `[ipv6]:port` или IP без порта
+fn parse_client(s: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, 0));
+    }
+    let (ip, port) = s.rsplit_once(':')?;
+    Some(SocketAddr::new(ip.parse().ok()?, port.parse().ok()?))
+}
+
+/// Открытый ключ в том виде, который ждёт ring: содержимое BIT STRING из SubjectPublicKeyInfo
+fn parse_public_key(key: &str) -> Result<Vec<u8>, String> {
+    let body: String = key.lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.split_whitespace())
+        .collect();
+    let der = BASE64.decode(body).map_err(|_| "public_key: не PEM и не base64".to_string())?;
+    spki_key(&der).ok_or_else(|| "public_key: ожидается DER SubjectPublicKeyInfo".to_string())
+}
+
+/// SubjectPublicKeyInfo ::= SEQUENCE { algorithm SEQUENCE, subjectPublicKey BIT STRING }
+fn spki_key(der: &[u8]) -> Option<Vec<u8>> {
+    let (spki, rest) = der_element(der, 0x30)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let (_algorithm, spki) = der_element(spki, 0x30)?;
+    let (bits, _) = der_element(spki, 0x03)?;
+    // Первый байт BIT STRING — число неиспользуемых битов, у ключей всегда 0
+    match bits.split_first()? {
+        (0, key) => Some(key.to_vec()),
+        _ => None,
+    }
+}
+
+/// Элемент DER с тегом `tag`: (содержимое, остаток)
+fn der_element(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&t, rest) = der.split_first()?;
+    if t != tag {
+        return None;
+    }
+    let (&first, mut rest) = rest.split_first()?;
+    let len = match first {
+        n if n < 0x80 => n as usize,
+        n => {
+            let bytes = (n & 0x7f) as usize;
+            if bytes == 0 || bytes > 4 || rest.len() < bytes {
+                return None;
+            }
+            let len = rest[..bytes].iter().fold(0usize, |len, &b| (len << 8) | b as usize);
+            rest = &rest[bytes..];
+            len
+        }
+    };
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    const NOW: u64 = 1_700_000_000;
+
+    struct Signer {
+        key: Ed25519KeyPair,
+    }
+
+    impl Signer {
+        fn new() -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            Self { key: Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap() }
+        }
+
+        /// PEM с SubjectPublicKeyInfo для Ed25519
+        fn public_pem(&self) -> String {
+            let mut der = vec![0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+            der.extend_from_slice(self.key.public_key().as_ref());
+            format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", BASE64.encode(der))
+        }
+
+        fn frontend(&self, require: bool) -> Frontend {
+            Frontend::new(&FrontendConfig {
+                scheme: FrontendScheme::Tcpshield,
+                algorithm: SignatureAlgorithm::Ed25519,
+                public_key: self.public_pem(),
+                max_age_secs: Some(5),
+                require,
+            }).unwrap()
+        }
+
+        fn address(&self, host: &str, client: &str, timestamp: impl std::fmt::Display) -> String {
+            let signed = format!("{}///{}///{}", host, client, timestamp);
+            let sig = BASE64.encode(self.key.sign(signed.as_bytes()));
+            format!("{}///{}", signed, sig)
+        }
+    }
+
+    fn decode_at(frontend: &Frontend, address: &str, now: u64) -> Result<Option<Decoded>, String> {
+        frontend.scheme.decode(address, UNIX_EPOCH + Duration::from_secs(now))
+    }
+
+    #[test]
+    fn valid_payload_gives_real_client_address() {
+        let signer = Signer::new();
+        let frontend = signer.frontend(true);
+        let decoded = decode_at(&frontend, &signer.address("play.example.com", "203.0.113.7:51234", NOW), NOW).unwrap().unwrap();
+        assert_eq!(decoded.host, "play.example.com");
+        assert_eq!(decoded.client, "203.0.113.7:51234".parse().unwrap());
+
+        let decoded = decode_at(&frontend, &signer.address("play.example.com", "2001:db8::1", NOW), NOW + 5).unwrap().unwrap();
+        assert_eq!(decoded.client, "[2001:db8::1]:0".parse().unwrap());
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let signer = Signer::new();
+        let frontend = signer.frontend(false);
+        let address = signer.address("play.example.com", "203.0.113.7:51234", NOW);
+
+        // Подменённый адрес клиента при той же подписи
+        let forged = address.replace("203.0.113.7", "198.51.100.1");
+        assert!(decode_at(&frontend, &forged, NOW).unwrap_err().contains("неверная подпись"));
+
+        // Подпись чужим ключом
+        let other = Signer::new().address("play.example.com", "203.0.113.7:51234", NOW);
+        assert!(decode_at(&frontend, &other, NOW).unwrap_err().contains("неверная подпись"));
+    }
+
+    #[test]
+    fn stale_and_future_timestamps_are_rejected() {
+        let signer = Signer::new();
+        let frontend = signer.frontend(false);
+        let stale = signer.address("play.example.com", "203.0.113.7:51234", NOW - 6);
+        let future = signer.address("play.example.com", "203.0.113.7:51234", NOW + 6);
+        assert!(decode_at(&frontend, &stale, NOW).unwrap_err().contains("метка времени"));
+        assert!(decode_at(&frontend, &future, NOW).unwrap_err().contains("метка времени"));
+    }
+
+    #[test]
+    fn malformed_key_or_payload_is_rejected() {
+        let config = |public_key: &str| FrontendConfig {
+            scheme: FrontendScheme::Tcpshield,
+            algorithm: SignatureAlgorithm::Ed25519,
+            public_key: public_key.to_string(),
+            max_age_secs: None,
+            require: false,
+        };
+        assert!(Frontend::new(&config("не ключ")).is_err());
+        assert!(Frontend::new(&config(&BASE64.encode([0x30, 0x03, 0x02, 0x01, 0x00]))).is_err());
+
+        let signer = Signer::new();
+        let frontend = signer.frontend(false);
+        assert!(decode_at(&frontend, "play.example.com///203.0.113.7:1///1///не-base64!", NOW).unwrap_err().contains("base64"));
+
+        // Подписанный, но с мусором вместо адреса клиента
+        let address = signer.address("play.example.com", "не-адрес", NOW);
+        assert!(decode_at(&frontend, &address, NOW).unwrap_err().contains("адрес клиента"));
+        let address = signer.address("play.example.com", "203.0.113.7:1", "вчера");
+        assert!(decode_at(&frontend, &address, NOW).unwrap_err().contains("метка времени"));
+    }
+
+    #[test]
+    fn plain_address_depends_on_require() {
+        let signer = Signer::new();
+        assert!(signer.frontend(false).decode("play.example.com").unwrap().is_none());
+        assert!(signer.frontend(true).decode("play.example.com").unwrap_err().contains("прямые подключения запрещены"));
+    }
+
+    #[test]
+    fn fml_markers_survive_decoding() {
+        let signer = Signer::new();
+        let frontend = signer.frontend(true);
+        let address = format!("{}\0FML2\0", signer.address("play.example.com", "203.0.113.7:51234", NOW));
+        let decoded = decode_at(&frontend, &address, NOW).unwrap().unwrap();
+        assert_eq!(decoded.host, "play.example.com\0FML2\0");
+    }
+}
@@ -0,0 +1,353 @@
+use std::io::{Error, ErrorKind, Result};
+use std::net::IpAddr;
+use uuid::{Builder, Uuid};
+
+use crate::proto::{VarInt, read_varint_string_from_slice};
+use crate::proto::varint::write_varint_string;
+
+/// next_state из Handshake
+pub const STATE_STATUS: i32 = 1;
+pub const STATE_LOGIN: i32 = 2;
+pub const STATE_TRANSFER: i32 = 3;
+
+/// Версии протокола, в которых менялся формат Login Start
+const PROTOCOL_1_19: i32 = 759;
+const PROTOCOL_1_19_1: i32 = 760;
+const PROTOCOL_1_19_3: i32 = 761;
+const PROTOCOL_1_20_2: i32 = 764;
+
+/// Пакет Handshake (id 0x00, состояние handshaking)
+#[derive(Clone, Debug)]
+pub struct Handshake {
+    pub protocol_version: i32,
+    pub server_address: String,
+    pub server_port: u16,
+    pub next_state: i32,
+}
+
+impl Handshake {
+    /// Разобрать тело пакета (packet id + поля, без префикса длины)
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let mut buf = body;
+        if VarInt::read_from_slice(&mut buf)? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "not a handshake packet"));
+        }
+        let protocol_version = VarInt::read_from_slice(&mut buf)?;
+        let server_address = read_varint_string_from_slice(&mut buf)?;
+        let server_port = u16::from_be_bytes(read_array(&mut buf)?);
+        let next_state = VarInt::read_from_slice(&mut buf)?;
+        Ok(Self { protocol_version, server_address, server_port, next_state })
+    }
+
+    pub fn is_status(&self) -> bool {
+        self.next_state == STATE_STATUS
+    }
+
+    pub fn is_login(&self) -> bool {
+        self.next_state == STATE_LOGIN || self.next_state == STATE_TRANSFER
+    }
+
+    /// Имя сервера для поиска маршрута: без меток после `\0` (FML, FML2, FML3 клиента Forge),
+    /// без порта (`host:25565`) и завершающей точки (адрес из SRV записи), в нижнем регистре
+    pub fn server_host(&self) -> String {
+        let host = self.server_address.split('\0').next().unwrap_or_default().trim();
+        let host = match host.strip_prefix('[') {
+            // IPv6 в скобках, возможно с портом
+            Some(v6) => v6.split(']').next().unwrap_or_default(),
+            // Одно двоеточие — порт; несколько — IPv6 без скобок
+            None => match host.rsplit_once(':') {
+                Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+                _ => host,
+            },
+        };
+        host.trim_end_matches('.').to_ascii_lowercase()
+    }
+
+    /// Имя маршрута: первая метка имени сервера (`fractal` из `fractal.example.com`);
+    /// None — имя сервера пустое
+    pub fn route_name(&self) -> Option<String> {
+        let host = self.server_host();
+        let name = host.split('.').next().unwrap_or_default();
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    /// Метки после `\0` в адресе сервера (маркер FML клиента Forge), вместе с первым `\0`
+    pub fn markers(&self) -> &str {
+        self.server_address.find('\0').map_or("", |i| &self.server_address[i..])
+    }
+
+    /// Тело пакета (packet id + поля, без префикса длины)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.server_address.len() + 16);
+        VarInt::write(0, &mut body);
+        VarInt::write(self.protocol_version, &mut body);
+        write_varint_string(&self.server_address, &mut body);
+        body.extend_from_slice(&self.server_port.to_be_bytes());
+        VarInt::write(self.next_state, &mut body);
+        body
+    }
+
+    /// Handshake с другими адресом и портом сервера. Метки после `\0` в исходном адресе
+    /// (маркер FML клиента Forge) сохраняются: по ним backend узнаёт модифицированный клиент.
+    pub fn with_server(&self, address: &str, port: u16) -> Self {
+        Self {
+            server_address: format!("{}{}", address, self.markers()),
+            server_port: port,
+            ..self.clone()
+        }
+    }
+
+    /// Handshake для backend с `bungeecord: true`: адрес сервера `host\0IP клиента\0UUID`.
+    /// Метки после `\0` в исходном адресе backend разобрать не может (он делит адрес по `\0`),
+    /// поэтому клиент Forge, как и в BungeeCord, передаётся свойством профиля `forgeClient`.
+    pub fn with_bungeecord_forwarding(&self, client: IpAddr, player: Uuid) -> Self {
+        let host = self.server_address.split('\0').next().unwrap_or_default();
+        let mut server_address = format!("{}\0{}\0{}", host, client.to_canonical(), player.simple());
+        if self.markers().contains("FML") {
+            server_address.push_str("\0[{\"name\":\"forgeClient\",\"value\":\"true\"}]");
+        }
+        Self { server_address, ..self.clone() }
+    }
+}
+
+/// Пакет Login Start (id 0x00, состояние login). Формат зависит от версии протокола.
+#[derive(Clone, Debug)]
+pub struct LoginStart {
+    pub name: String,
+    pub uuid: Option<Uuid>,
+}
+
+impl LoginStart {
+    pub fn decode(body: &[u8], protocol_version: i32) -> Result<Self> {
+        let mut buf = body;
+        if VarInt::read_from_slice(&mut buf)? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "not a login start packet"));
+        }
+        let name = read_varint_string_from_slice(&mut buf)?;
+
+        let uuid = match protocol_version {
+            v if v >= PROTOCOL_1_20_2 => Some(read_uuid(&mut buf)?),
+            v if v >= PROTOCOL_1_19_3 => read_optional_uuid(&mut buf)?,
+            v if v >= PROTOCOL_1_19 => {
+                // 1.19 - 1.19.2: необязательные данные подписи перед UUID
+                if read_bool(&mut buf)? {
+                    let _timestamp: [u8; 8] = read_array(&mut buf)?;
+                    skip_byte_array(&mut buf)?;
+                    skip_byte_array(&mut buf)?;
+                }
+                if v >= PROTOCOL_1_19_1 { read_optional_uuid(&mut buf)? } else { None }
+            }
+            _ => None,
+        };
+
+        Ok(Self { name, uuid })
+    }
+
+    /// UUID игрока: переданный клиентом, иначе offline-mode UUID по имени
+    pub fn player_uuid(&self) -> Uuid {
+        self.uuid.unwrap_or_else(|| offline_uuid(&self.name))
+    }
+}
+
+/// Offline-mode UUID как у сервера: UUID v3 от "OfflinePlayer:<name>"
+pub fn offline_uuid(name: &str) -> Uuid {
+    let digest = md5::compute(format!("OfflinePlayer:{}", name));
+    Builder::from_md5_bytes(digest.0).into_uuid()
+}
+
+fn read_array<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N]> {
+    if buf.len() < N {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected eof"));
+    }
+    let mut out = [0u8; N];
+    out.copy_from_slice(&buf[..N]);
+    *buf = &buf[N..];
+    Ok(out)
+}
+
+fn read_bool(buf: &mut &[u8]) -> Result<bool> {
+    let [b] = read_array::<1>(buf)?;
+    Ok(b != 0)
+}
+
+fn read_uuid(buf: &mut &[u8]) -> Result<Uuid> {
+    Ok(Uuid::from_bytes(read_array(buf)?))
+}
+
+fn read_optional_uuid(buf: &mut &[u8]) -> Result<Option<Uuid>> {
+    if read_bool(buf)? { Ok(Some(read_uuid(buf)?)) } else { Ok(None) }
+}
+
+fn skip_byte_array(buf: &mut &[u8]) -> Result<()> {
+    let len = VarInt::read_from_slice(buf)?;
+    if len < 0 || buf.len() < len as usize {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid byte array length"));
+    }
+    *buf = &buf[len as usize..];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(server_address: &str) -> Handshake {
+        Handshake { protocol_version: 767, server_address: server_address.to_string(), server_port: 25565, next_state: STATE_LOGIN }
+    }
+
+    #[test]
+    fn client_styles_resolve_to_same_route() {
+        let styles = [
+            "host.example.com",
+            "host\0FML\0",
+            "host\0FML2\0",
+            "host.example.com\0FML3\0",
+            "host.",
+            "host.example.com.",
+            "HOST.Example.com",
+            "host:25565",
+            "host.example.com.:25565",
+            " host.example.com ",
+        ];
+        for address in styles {
+            assert_eq!(handshake(address).route_name().as_deref(), Some("host"), "{:?}", address);
+        }
+        assert_eq!(handshake("Host.Example.COM.\0FML2\0").server_host(), "host.example.com");
+    }
+
+    #[test]
+    fn ipv6_literals_resolve_to_same_route() {
+        for address in ["[::1]:25565", "[::1]", "::1"] {
+            assert_eq!(handshake(address).server_host(), "::1", "{:?}", address);
+            assert_eq!(handshake(address).route_name().as_deref(), Some("::1"), "{:?}", address);
+        }
+        // Последняя группа IPv6 без скобок не принимается за порт
+        assert_eq!(handshake("2001:DB8::25565").server_host(), "2001:db8::25565");
+    }
+
+    #[test]
+    fn empty_server_address_has_no_route() {
+        for address in ["", ".", "\0FML\0", ":25565"] {
+            assert_eq!(handshake(address).route_name(), None, "{:?}", address);
+        }
+    }
+
+    #[test]
+    fn markers_are_split_off() {
+        assert_eq!(handshake("host.example.com").markers(), "");
+        assert_eq!(handshake("host.example.com\0FML2\0").markers(), "\0FML2\0");
+    }
+
+    #[test]
+    fn with_server_keeps_markers_in_encoded_packet() {
+        for markers in ["", "\0FML\0", "\0FML2\0", "\0FML3\0"] {
+            let original = handshake(&format!("HOST.example.com.{}", markers));
+            let encoded = original.with_server("backend.local", 25570).encode();
+
+            let decoded = Handshake::decode(&encoded).unwrap();
+            assert_eq!(decoded.server_address, format!("backend.local{}", markers));
+            assert_eq!(decoded.server_port, 25570);
+            assert_eq!(decoded.protocol_version, original.protocol_version);
+            assert_eq!(decoded.next_state, original.next_state);
+
+            // Строка адреса в пакете: VarInt длины, затем байты адреса с метками как есть
+            let mut expected = Vec::new();
+            write_varint_string(&format!("backend.local{}", markers), &mut expected);
+            assert!(encoded.windows(expected.len()).any(|w| w == expected.as_slice()), "{:?}", markers);
+        }
+    }
+
+    /// Тело Login Start: имя и поля после него в формате нужной версии
+    fn login_start(name: &str, tail: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        VarInt::write(0, &mut body);
+        write_varint_string(name, &mut body);
+        body.extend_from_slice(tail);
+        body
+    }
+
+    const PLAYER: Uuid = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+
+    #[test]
+    fn login_start_before_1_19_has_only_name() {
+        let login = LoginStart::decode(&login_start("Steve", &[]), 758).unwrap();
+        assert_eq!(login.name, "Steve");
+        assert_eq!(login.uuid, None);
+        assert_eq!(login.player_uuid(), offline_uuid("Steve"));
+    }
+
+    #[test]
+    fn login_start_1_19_skips_signature_data() {
+        let mut tail = vec![1];
+        tail.extend_from_slice(&[0u8; 8]);
+        for len in [3, 2] {
+            VarInt::write(len, &mut tail);
+            tail.extend(std::iter::repeat_n(0xAA, len as usize));
+        }
+        let login = LoginStart::decode(&login_start("Steve", &tail), PROTOCOL_1_19).unwrap();
+        assert_eq!(login.name, "Steve");
+        assert_eq!(login.uuid, None);
+    }
+
+    #[test]
+    fn login_start_1_19_1_has_optional_uuid_after_signature() {
+        let mut tail = vec![0, 1];
+        tail.extend_from_slice(PLAYER.as_bytes());
+        let login = LoginStart::decode(&login_start("Steve", &tail), PROTOCOL_1_19_1).unwrap();
+        assert_eq!(login.uuid, Some(PLAYER));
+
+        let login = LoginStart::decode(&login_start("Steve", &[0, 0]), PROTOCOL_1_19_1).unwrap();
+        assert_eq!(login.uuid, None);
+    }
+
+    #[test]
+    fn login_start_1_19_3_has_optional_uuid() {
+        let mut tail = vec![1];
+        tail.extend_from_slice(PLAYER.as_bytes());
+        let login = LoginStart::decode(&login_start("Steve", &tail), PROTOCOL_1_19_3).unwrap();
+        assert_eq!(login.uuid, Some(PLAYER));
+        assert_eq!(login.player_uuid(), PLAYER);
+    }
+
+    #[test]
+    fn login_start_1_20_2_has_uuid() {
+        let login = LoginStart::decode(&login_start("Steve", PLAYER.as_bytes()), PROTOCOL_1_20_2).unwrap();
+        assert_eq!(login.name, "Steve");
+        assert_eq!(login.uuid, Some(PLAYER));
+    }
+
+    #[test]
+    fn login_start_truncated_is_an_error() {
+        let body = login_start("Steve", &PLAYER.as_bytes()[..10]);
+        assert_eq!(LoginStart::decode(&body, PROTOCOL_1_20_2).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        // Обрезанная подпись 1.19: длина массива больше остатка
+        let mut tail = vec![1];
+        tail.extend_from_slice(&[0u8; 8]);
+        VarInt::write(100, &mut tail);
+        assert!(LoginStart::decode(&login_start("Steve", &tail), PROTOCOL_1_19).is_err());
+        // Обрезанное имя
+        assert!(LoginStart::decode(&login_start("Steve", &[])[..4], PROTOCOL_1_20_2).is_err());
+        // Не Login Start
+        assert!(LoginStart::decode(&[1], PROTOCOL_1_20_2).is_err());
+    }
+
+    #[test]
+    fn bungeecord_forwarding_appends_client_and_uuid() {
+        let player = offline_uuid("Steve");
+        let forwarded = handshake("mc.example.com").with_bungeecord_forwarding("::ffff:192.0.2.7".parse().unwrap(), player);
+        assert_eq!(forwarded.server_address, format!("mc.example.com\0192.0.2.7\0{}", player.simple()));
+    }
+
+    #[test]
+    fn bungeecord_forwarding_marks_forge_clients() {
+        let player = offline_uuid("Steve");
+        for markers in ["\0FML\0", "\0FML2\0", "\0FML3\0"] {
+            let forwarded = handshake(&format!("mc.example.com{}", markers))
+                .with_bungeecord_forwarding("192.0.2.7".parse().unwrap(), player);
+            assert_eq!(
+                forwarded.server_address,
+                format!("mc.example.com\0192.0.2.7\0{}\0[{{\"name\":\"forgeClient\",\"value\":\"true\"}}]", player.simple()),
+            );
+        }
+    }
+}
@@ -1,12 +1,33 @@
 pub mod router;
+pub mod acceptor;
+pub mod route;
+pub mod socket_options;
+#[cfg(target_os = "linux")]
+pub mod transparent;
 pub mod rate_limiter;
 pub mod tcp_proxy;
 pub mod varint;
 pub mod udp_proxy;
-// mod connection_Handler;
+pub mod udp_flow;
+pub mod udp_batch;
+pub mod handshake;
+pub mod packet;
+pub mod status;
+pub mod proxy_protocol;
+pub mod forward;
+pub mod frontend;
+#[cfg(target_os = "linux")]
+pub mod splice;
+pub mod voicechat;
 
+pub use acceptor::{Acceptor, OverflowPolicy};
 pub use udp_proxy::UdpProxy;
-pub use router::Router;
+pub use router::{Router, UdpSessionGuard, UdpSessions};
+pub use route::Route;
+pub use socket_options::SocketOptions;
+pub use frontend::{Frontend, FrontendConfig};
 pub use rate_limiter::RateLimiter;
 pub use tcp_proxy::TcpProxy;
+pub use handshake::{Handshake, LoginStart};
+pub use voicechat::VoicePacketHeader;
 pub use varint::{VarInt, read_varint_string_from_slice};
\ No newline at end of file
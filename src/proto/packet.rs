@@ -0,0 +1,48 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::io::Result;
+
+use crate::proto::VarInt;
+use crate::consts::MAX_PACKET_LEN;
+
+/// Read one full length-prefixed packet: raw bytes are appended to `raw`, the body is returned.
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R, raw: &mut Vec<u8>) -> Result<Vec<u8>> {
+    // VarInt length prefix, at most 5 bytes
+    let mut len_prefix = Vec::with_capacity(5);
+    loop {
+        if len_prefix.len() == 5 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "length varint too big"));
+        }
+        let b = reader.read_u8().await?;
+        len_prefix.push(b);
+        if b & 0x80 == 0 {
+            break;
+        }
+    }
+
+    let mut prefix_slice: &[u8] = &len_prefix;
+    let length = VarInt::read_from_slice(&mut prefix_slice)?;
+    if length < 0 || length as usize > MAX_PACKET_LEN {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "packet too large"));
+    }
+
+    let mut body = vec![0u8; length as usize];
+    reader.read_exact(&mut body).await?;
+
+    raw.extend_from_slice(&len_prefix);
+    raw.extend_from_slice(&body);
+    Ok(body)
+}
+
+/// Prefix a packet body with its VarInt length.
+pub fn encode_packet(body: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(body.len() + 5);
+    VarInt::write(body.len() as i32, &mut buf);
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// Write one packet body with its VarInt length prefix.
+pub async fn write_packet<W: AsyncWrite + Unpin>(writer: &mut W, body: &[u8]) -> Result<()> {
+    writer.write_all(&encode_packet(body)).await?;
+    writer.flush().await
+}
@@ -0,0 +1,110 @@
+use std::net::{IpAddr, SocketAddr};
+
+use crate::proto::route::ProxyProtocol;
+
+const V2_SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+/// Версия 2, команда PROXY
+const V2_VERSION_PROXY: u8 = 0x21;
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+/// Заголовок PROXY protocol, который отправляется upstream перед первыми байтами клиента.
+/// src — адрес клиента, dst — адрес, на который клиент подключился к прокси.
+pub fn header(version: ProxyProtocol, src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    // Оба адреса должны быть одного семейства: при смешении IPv4 отображается в IPv6
+    let (src_ip, dst_ip) = match (src.ip().to_canonical(), dst.ip().to_canonical()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => (IpAddr::V4(s), IpAddr::V4(d)),
+        (s, d) => (IpAddr::V6(to_v6(s)), IpAddr::V6(to_v6(d))),
+    };
+    match version {
+        ProxyProtocol::V1 => {
+            let family = if src_ip.is_ipv4() { "TCP4" } else { "TCP6" };
+            format!("PROXY {} {} {} {} {}\r\n", family, src_ip, dst_ip, src.port(), dst.port()).into_bytes()
+        }
+        ProxyProtocol::V2 => {
+            let mut buf = Vec::with_capacity(16 + 36);
+            buf.extend_from_slice(&V2_SIGNATURE);
+            buf.push(V2_VERSION_PROXY);
+            match (src_ip, dst_ip) {
+                (IpAddr::V4(s), IpAddr::V4(d)) => {
+                    buf.push(V2_TCP4);
+                    buf.extend_from_slice(&12u16.to_be_bytes());
+                    buf.extend_from_slice(&s.octets());
+                    buf.extend_from_slice(&d.octets());
+                }
+                (s, d) => {
+                    buf.push(V2_TCP6);
+                    buf.extend_from_slice(&36u16.to_be_bytes());
+                    buf.extend_from_slice(&to_v6(s).octets());
+                    buf.extend_from_slice(&to_v6(d).octets());
+                }
+            }
+            buf.extend_from_slice(&src.port().to_be_bytes());
+            buf.extend_from_slice(&dst.port().to_be_bytes());
+            buf
+        }
+    }
+}
+
+fn to_v6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn v1_ipv4() {
+        let header = header(ProxyProtocol::V1, addr("192.0.2.1:51000"), addr("198.51.100.2:25565"));
+        assert_eq!(header, b"PROXY TCP4 192.0.2.1 198.51.100.2 51000 25565\r\n");
+    }
+
+    #[test]
+    fn v1_ipv6() {
+        let header = header(ProxyProtocol::V1, addr("[2001:db8::1]:51000"), addr("[2001:db8::2]:25565"));
+        assert_eq!(header, b"PROXY TCP6 2001:db8::1 2001:db8::2 51000 25565\r\n");
+    }
+
+    #[test]
+    fn v1_mixed_families_use_ipv6() {
+        let mixed = header(ProxyProtocol::V1, addr("192.0.2.1:51000"), addr("[2001:db8::2]:25565"));
+        assert_eq!(mixed, b"PROXY TCP6 ::ffff:192.0.2.1 2001:db8::2 51000 25565\r\n");
+        // IPv4, отображённый в IPv6 (двухстековый сокет), остаётся IPv4
+        let mapped = header(ProxyProtocol::V1, addr("[::ffff:192.0.2.1]:51000"), addr("[::ffff:198.51.100.2]:25565"));
+        assert_eq!(mapped, b"PROXY TCP4 192.0.2.1 198.51.100.2 51000 25565\r\n");
+    }
+
+    #[test]
+    fn v2_ipv4() {
+        let header = header(ProxyProtocol::V2, addr("192.0.2.1:51000"), addr("198.51.100.2:25565"));
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        expected.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 2]);
+        expected.extend_from_slice(&51000u16.to_be_bytes());
+        expected.extend_from_slice(&25565u16.to_be_bytes());
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn v2_ipv6() {
+        let src = addr("[2001:db8::1]:51000");
+        let dst = addr("[2001:db8::2]:25565");
+        let header = header(ProxyProtocol::V2, src, dst);
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        expected.extend_from_slice(&to_v6(src.ip()).octets());
+        expected.extend_from_slice(&to_v6(dst.ip()).octets());
+        expected.extend_from_slice(&51000u16.to_be_bytes());
+        expected.extend_from_slice(&25565u16.to_be_bytes());
+        assert_eq!(header.len(), 16 + 36);
+        assert_eq!(header, expected);
+    }
+}
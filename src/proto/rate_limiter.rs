@@ -1,4 +1,4 @@
-use tokio::time::Instant;
+use tokio::time::{sleep, Duration, Instant};
 pub struct RateLimiter {
     capacity: usize,
     tokens: f64,
@@ -21,11 +21,23 @@ impl RateLimiter {
         let refill = elapsed * self.refill_per_sec;
         self.tokens = (self.tokens + refill).min(self.capacity as f64);
         self.last = now;
-        if (self.tokens as f64) >= (n as f64) {
+        if self.tokens >= n as f64 {
             self.tokens -= n as f64;
             true
         } else {
             false
         }
     }
-}
\ No newline at end of file
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Дождаться, пока станет доступно n токенов (n не больше capacity), и списать их
+    pub async fn acquire(&mut self, n: usize) {
+        while !self.allow(n) {
+            let deficit = n as f64 - self.tokens;
+            sleep(Duration::from_secs_f64((deficit / self.refill_per_sec).max(0.001))).await;
+        }
+    }
+}
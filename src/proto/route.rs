@@ -0,0 +1,259 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::proto::{Handshake, LoginStart, SocketOptions};
+use crate::proto::handshake::offline_uuid;
+
+/// Маршрут: поддомен -> upstream адреса и параметры маршрута
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub name: String,
+    pub tcp: SocketAddr,
+    /// None — маршрут без UDP
+    pub udp: Option<SocketAddr>,
+    /// Имя хоста upstream, если он задан не IP (адреса выше — результат его разрешения)
+    pub host: Option<String>,
+    pub options: RouteOptions,
+}
+
+impl Route {
+    pub fn new(name: String, tcp: SocketAddr, udp: Option<SocketAddr>) -> Self {
+        Self { name, tcp, udp, host: None, options: RouteOptions::default() }
+    }
+}
+
+/// Параметры маршрута из блока `options`
+#[derive(Clone, Debug, Default)]
+pub struct RouteOptions {
+    /// Ограничение скорости TCP сессии в каждом направлении
+    pub rate_limit: Option<RateLimit>,
+    pub timeouts: Timeouts,
+    /// Отправлять upstream заголовок PROXY protocol с реальным адресом клиента
+    pub proxy_protocol: Option<ProxyProtocol>,
+    /// Ответ на запрос статуса (и текст отключения при входе), когда upstream недоступен
+    pub motd: Option<String>,
+    pub access: AccessList,
+    /// Параметры сокета подключения к upstream
+    pub socket: SocketOptions,
+    /// Подменять адрес и порт сервера в Handshake, отправляемом upstream
+    pub rewrite_handshake: Option<HandshakeRewrite>,
+    /// Передавать upstream адрес клиента и UUID игрока в Handshake
+    pub forwarding: Option<IpForwarding>,
+    /// Подключаться к upstream (TCP и UDP) с адреса клиента (Linux, нужен CAP_NET_ADMIN)
+    pub transparent: bool,
+    /// Пересылать TCP через splice(2) без копирования в userspace (Linux; иначе обычное копирование)
+    pub splice: bool,
+}
+
+impl RouteOptions {
+    /// UUID, под которым игрока знает backend: с BungeeCord forwarding — offline UUID,
+    /// который прокси дописывает в Handshake, иначе присланный клиентом.
+    /// Его же Simple Voice Chat кладёт в UDP пакеты
+    pub fn backend_player_uuid(&self, login: &LoginStart) -> Uuid {
+        match self.forwarding {
+            Some(IpForwarding::BungeeCord) => offline_uuid(&login.name),
+            None => login.player_uuid(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub bytes_per_sec: usize,
+    pub burst_bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeouts {
+    /// Таймаут подключения к upstream (None — значение по умолчанию)
+    pub connect: Option<Duration>,
+    /// Разрыв сессии без трафика в обе стороны дольше этого времени
+    pub idle: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyProtocol {
+    V1,
+    V2,
+}
+
+impl FromStr for ProxyProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" | "1" => Ok(Self::V1),
+            "v2" | "2" => Ok(Self::V2),
+            _ => Err(format!("неизвестная версия PROXY protocol '{}' (ожидается v1 или v2)", s)),
+        }
+    }
+}
+
+impl fmt::Display for ProxyProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        })
+    }
+}
+
+/// Что подставить в Handshake вместо адреса и порта, к которым подключался клиент
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeRewrite {
+    /// Адрес и порт самого upstream, как если бы клиент подключался к нему напрямую
+    /// (имя хоста или цель SRV, если upstream задан именем)
+    Upstream,
+    /// Заданные значения; незаданное поле остаётся как у клиента
+    Fixed { address: Option<String>, port: Option<u16> },
+}
+
+impl HandshakeRewrite {
+    pub fn apply(&self, handshake: &Handshake, route: &Route) -> Handshake {
+        match self {
+            Self::Upstream => {
+                let address = route.host.clone().unwrap_or_else(|| route.tcp.ip().to_string());
+                handshake.with_server(&address, route.tcp.port())
+            }
+            Self::Fixed { address, port } => handshake.with_server(
+                address.as_deref().unwrap_or(handshake.server_address.split('\0').next().unwrap_or_default()),
+                port.unwrap_or(handshake.server_port),
+            ),
+        }
+    }
+}
+
+/// Способ передачи upstream адреса клиента внутри протокола Minecraft
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpForwarding {
+    /// Legacy BungeeCord (`bungeecord: true` в spigot.yml): данные дописываются в адрес сервера Handshake
+    BungeeCord,
+}
+
+impl FromStr for IpForwarding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bungeecord" => Ok(Self::BungeeCord),
+            _ => Err(format!("неизвестный способ forwarding '{}' (ожидается bungeecord)", s)),
+        }
+    }
+}
+
+impl fmt::Display for IpForwarding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::BungeeCord => "bungeecord",
+        })
+    }
+}
+
+/// Списки доступа по IP клиента: deny проверяется первым, пустой allow разрешает всех
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+}
+
+impl AccessList {
+    pub fn permits(&self, ip: &IpAddr) -> bool {
+        if self.deny.iter().any(|n| n.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|n| n.contains(ip))
+    }
+}
+
+/// Подсеть в нотации CIDR ("10.0.0.0/8"); одиночный адрес — подсеть /32 или /128
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_eq(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let full = (prefix / 8) as usize;
+    let rest = prefix % 8;
+    if net[..full] != ip[..full] {
+        return false;
+    }
+    if rest == 0 {
+        return true;
+    }
+    let mask = 0xFFu8 << (8 - rest);
+    net[full] & mask == ip[full] & mask
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("неверный IP адрес в '{}'", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|p| *p <= max)
+                .ok_or_else(|| format!("неверная длина префикса в '{}' (0..={})", s, max))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(uuid: Option<Uuid>) -> LoginStart {
+        LoginStart { name: "Steve".to_string(), uuid }
+    }
+
+    #[test]
+    fn backend_uuid_is_client_uuid_without_forwarding() {
+        let mojang = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        let options = RouteOptions::default();
+        assert_eq!(options.backend_player_uuid(&login(Some(mojang))), mojang);
+        assert_eq!(options.backend_player_uuid(&login(None)), offline_uuid("Steve"));
+    }
+
+    #[test]
+    fn backend_uuid_is_forwarded_offline_uuid_with_bungeecord() {
+        let mojang = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        let options = RouteOptions { forwarding: Some(IpForwarding::BungeeCord), ..RouteOptions::default() };
+        let uuid = options.backend_player_uuid(&login(Some(mojang)));
+        assert_eq!(uuid, offline_uuid("Steve"));
+
+        // UUID UDP сессии совпадает с тем, что уходит backend в Handshake
+        let handshake = Handshake { protocol_version: 767, server_address: "mc.example.com".to_string(), server_port: 25565, next_state: 2 };
+        let forwarded = handshake.with_bungeecord_forwarding("192.0.2.7".parse().unwrap(), uuid);
+        assert!(forwarded.server_address.ends_with(&uuid.simple().to_string()));
+    }
+}
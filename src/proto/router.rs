@@ -1,90 +1,438 @@
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::net::{SocketAddr, IpAddr};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Clone, Debug)]
-pub struct Route {
-    pub tcp: String, // "ip:port"
-    pub udp: String, // "ip:port"
+use crate::proto::Route;
+
+/// Неизменяемый снимок маршрутов с вторичными индексами.
+/// Читается без блокировок; любое изменение строит новый снимок и атомарно его подменяет.
+#[derive(Default)]
+struct RouteTable {
+    by_name: HashMap<String, Arc<Route>>,
+    /// upstream IP (tcp или udp) -> маршруты на этом IP
+    by_upstream_ip: HashMap<IpAddr, Vec<Arc<Route>>>,
+    /// upstream адрес (tcp или udp, IP+порт) -> маршрут
+    by_upstream_addr: HashMap<SocketAddr, Arc<Route>>,
+}
+
+impl RouteTable {
+    fn build<I: IntoIterator<Item = Arc<Route>>>(routes: I) -> Self {
+        let mut table = Self::default();
+        for route in routes {
+            for addr in std::iter::once(route.tcp).chain(route.udp) {
+                table.by_upstream_addr.insert(addr, route.clone());
+            }
+            let ips = table.by_upstream_ip.entry(route.tcp.ip()).or_default();
+            ips.push(route.clone());
+            if let Some(udp) = route.udp && udp.ip() != route.tcp.ip() {
+                table.by_upstream_ip.entry(udp.ip()).or_default().push(route.clone());
+            }
+            // Имя сервера из Handshake приводится к нижнему регистру
+            table.by_name.insert(route.name.to_ascii_lowercase(), route);
+        }
+        table
+    }
+}
+
+/// Снимок UDP сессий для передачи новому процессу при обновлении бинаря
+#[derive(Default, Serialize, Deserialize)]
+pub struct UdpSessions {
+    pub clients: Vec<(IpAddr, SocketAddr)>,
+    pub players: Vec<(Uuid, SocketAddr)>,
+}
+
+/// UDP upstream, выбранный при TCP рукопожатии
+struct UdpSession {
+    upstream: SocketAddr,
+    /// Номер регистрации: завершившаяся TCP сессия снимает запись, только если её
+    /// не перезаписала более новая
+    generation: u64,
+    owner: SessionOwner,
+    /// IP клиента TCP сессии, зарегистрировавшей запись (None — восстановлена из снимка)
+    client_ip: Option<IpAddr>,
+}
+
+#[derive(Clone, Copy)]
+enum SessionOwner {
+    /// Принадлежит живой TCP сессии
+    Session,
+    /// TCP сессия завершилась в этот момент; запись ждёт переподключения до истечения grace
+    Released(Instant),
+    /// Получена от прежнего процесса при обновлении, владельца нет: живёт, пока используется
+    /// (последнее использование)
+    Orphan(Instant),
 }
 
-#[derive(Clone)]
 pub struct Router {
-    routes: Arc<Mutex<HashMap<String, Route>>>,
-    /// Точные сопоставления client SocketAddr -> upstream SocketAddr (IP+порт)
-    client_udp_map: Arc<Mutex<HashMap<SocketAddr, SocketAddr>>>,
-    /// Сопоставления по IP (без портов): client_ip -> upstream_ip
-    client_upstream_ip_map: Arc<Mutex<HashMap<IpAddr, IpAddr>>>,
+    routes: ArcSwap<RouteTable>,
+    /// UDP сессии по IP клиента
+    client_udp_sessions: DashMap<IpAddr, UdpSession>,
+    /// UDP сессии по UUID игрока (из Login Start): нужны, когда за одним IP игроки разных серверов
+    player_udp_sessions: DashMap<Uuid, UdpSession>,
+    next_generation: AtomicU64,
+    /// Растёт при удалении UDP сессии или смене её upstream: по нему UDP прокси
+    /// понимает, что открытые потоки пора перепроверить
+    udp_sessions_epoch: AtomicU64,
+}
+
+/// Владение UDP сессиями TCP сессии игрока. При drop сессии не удаляются сразу, а
+/// освобождаются: переподключившийся игрок продолжает говорить без перерыва, а через
+/// grace период их удаляет `expire_udp_sessions`.
+pub struct UdpSessionGuard {
+    router: Arc<Router>,
+    client_ip: Option<IpAddr>,
+    player: Option<Uuid>,
+    generation: u64,
+}
+
+impl Drop for UdpSessionGuard {
+    fn drop(&mut self) {
+        if let Some(client_ip) = &self.client_ip {
+            release(&self.router.client_udp_sessions, client_ip, self.generation);
+        }
+        if let Some(player) = &self.player {
+            release(&self.router.player_udp_sessions, player, self.generation);
+        }
+    }
+}
+
+fn release<K: Eq + Hash>(sessions: &DashMap<K, UdpSession>, key: &K, generation: u64) {
+    if let Some(mut session) = sessions.get_mut(key)
+        && session.generation == generation
+    {
+        session.owner = SessionOwner::Released(Instant::now());
+    }
+}
+
+/// Upstream сессии; у сессии без владельца продлевает срок жизни
+fn touch<K: Eq + Hash>(sessions: &DashMap<K, UdpSession>, key: &K) -> Option<SocketAddr> {
+    sessions.get_mut(key).map(|mut session| {
+        if let SessionOwner::Orphan(used) = &mut session.owner {
+            *used = Instant::now();
+        }
+        session.upstream
+    })
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Router {
     pub fn new() -> Self {
         Self {
-            routes: Arc::new(Mutex::new(HashMap::new())),
-            client_udp_map: Arc::new(Mutex::new(HashMap::new())),
-            client_upstream_ip_map: Arc::new(Mutex::new(HashMap::new())),
+            routes: ArcSwap::from_pointee(RouteTable::default()),
+            client_udp_sessions: DashMap::new(),
+            player_udp_sessions: DashMap::new(),
+            next_generation: AtomicU64::new(0),
+            udp_sessions_epoch: AtomicU64::new(0),
+        }
+    }
+
+    /// Добавить/обновить маршрут
+    pub fn add_route(&self, route: Route) {
+        let route = Arc::new(route);
+        self.routes.rcu(|table| {
+            let others = table.by_name.values().filter(|r| !r.name.eq_ignore_ascii_case(&route.name)).cloned();
+            RouteTable::build(others.chain([route.clone()]))
+        });
+    }
+
+    /// Атомарно заменить весь набор маршрутов
+    pub fn replace_routes(&self, routes: Vec<Route>) {
+        self.routes.store(Arc::new(RouteTable::build(routes.into_iter().map(Arc::new))));
+    }
+
+    pub fn route_count(&self) -> usize {
+        self.routes.load().by_name.len()
+    }
+
+    /// Маршрут по имени без учёта регистра (см. `Handshake::server_host`)
+    pub fn lookup_route(&self, server_name: &str) -> Option<Arc<Route>> {
+        self.routes.load().by_name.get(server_name).cloned()
+    }
+
+    /// Все маршруты, у которых tcp или udp upstream на заданном IP
+    pub fn routes_for_upstream_ip(&self, ip: &IpAddr) -> Vec<Arc<Route>> {
+        self.routes.load().by_upstream_ip.get(ip).cloned().unwrap_or_default()
+    }
+
+    /// Маршрут, которому принадлежит upstream адрес (tcp или udp)
+    pub fn route_for_upstream(&self, addr: &SocketAddr) -> Option<Arc<Route>> {
+        self.routes.load().by_upstream_addr.get(addr).cloned()
+    }
+
+    /// Зарегистрировать UDP upstream маршрута для TCP сессии: по IP клиента и, если
+    /// известен, по UUID игрока. Сессии принадлежат TCP сессии, пока жив guard.
+    pub fn claim_udp_session(self: &Arc<Self>, client_ip: Option<IpAddr>, player: Option<Uuid>, upstream: SocketAddr) -> UdpSessionGuard {
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        let client_ip = client_ip.map(|ip| ip.to_canonical());
+        if let Some(client_ip) = client_ip {
+            self.insert_udp_session(&self.client_udp_sessions, client_ip, upstream, generation, SessionOwner::Session, Some(client_ip));
+            println!("REGISTER UDP session: {} -> {}", client_ip, upstream);
+        }
+        if let Some(player) = player {
+            self.insert_udp_session(&self.player_udp_sessions, player, upstream, generation, SessionOwner::Session, client_ip);
+            println!("REGISTER UDP session: player {} -> {}", player, upstream);
+        }
+        UdpSessionGuard { router: self.clone(), client_ip, player, generation }
+    }
+
+    fn insert_udp_session<K: Eq + Hash>(
+        &self,
+        sessions: &DashMap<K, UdpSession>,
+        key: K,
+        upstream: SocketAddr,
+        generation: u64,
+        owner: SessionOwner,
+        client_ip: Option<IpAddr>,
+    ) {
+        let prev = sessions.insert(key, UdpSession { upstream, generation, owner, client_ip });
+        if prev.is_some_and(|prev| prev.upstream != upstream) {
+            self.udp_sessions_epoch.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    /// Добавить/обновить маршрут с указанием tcp и udp адресов
-    pub fn add_route(&self, name: String, tcp_addr: String, udp_addr: String) {
-        let mut guard = self.routes.lock().unwrap();
-        guard.insert(name, Route { tcp: tcp_addr, udp: udp_addr });
+    /// Получить UDP upstream для client_ip
+    pub fn lookup_udp_session(&self, client_ip: &IpAddr) -> Option<SocketAddr> {
+        touch(&self.client_udp_sessions, client_ip)
     }
 
-    pub fn lookup_route(&self, server_name: &str) -> Option<Route> {
-        let guard = self.routes.lock().unwrap();
-        guard.get(server_name).cloned()
+    /// Получить UDP upstream для игрока
+    pub fn lookup_player_udp_session(&self, player: &Uuid) -> Option<SocketAddr> {
+        touch(&self.player_udp_sessions, player)
     }
 
-    /// Получить все upstream UDP адреса (ip:port) для заданного upstream IP
-    pub fn upstream_addrs_for_ip(&self, ip: &IpAddr) -> Vec<SocketAddr> {
-        let guard = self.routes.lock().unwrap();
-        guard.values()
-            .filter_map(|r| r.udp.parse::<SocketAddr>().ok())
-            .filter(|sa| &sa.ip() == ip)
-            .collect()
+    /// Сессия игрока зарегистрирована TCP сессией с этого IP. UUID в пакете голосового чата
+    /// ничем не подписан: только так видно, что его прислал сам игрок, а не чужой клиент
+    pub fn player_udp_session_from(&self, player: &Uuid, client_ip: &IpAddr) -> bool {
+        self.player_udp_sessions.get(player).is_some_and(|s| s.client_ip == Some(client_ip.to_canonical()))
     }
 
-    /// Регистрация соответствия client -> upstream udp (точное по SocketAddr)
-    pub fn register_udp_mapping(&self, client: SocketAddr, upstream: SocketAddr) {
-        let mut guard = self.client_udp_map.lock().unwrap();
-        guard.insert(client, upstream);
-        println!("REGISTER UDP mapping: {} -> {}", client, upstream);
+    /// Сессия, по которой был открыт UDP поток, всё ещё ведёт на тот же upstream
+    pub fn udp_session_is(&self, client_ip: &IpAddr, player: Option<&Uuid>, upstream: SocketAddr) -> bool {
+        match player {
+            Some(player) => self.player_udp_sessions.get(player).is_some_and(|s| s.upstream == upstream),
+            None => self.client_udp_sessions.get(client_ip).is_some_and(|s| s.upstream == upstream),
+        }
     }
 
-    /// Удалить соответствие клиента (точное по SocketAddr)
-    pub fn unregister_udp_mapping(&self, client: &SocketAddr) {
-        let mut guard = self.client_udp_map.lock().unwrap();
-        if guard.remove(client).is_some() {
-            println!("UNREGISTER UDP mapping: {}", client);
+    /// Меняется при каждом удалении UDP сессии или смене её upstream
+    pub fn udp_sessions_epoch(&self) -> u64 {
+        self.udp_sessions_epoch.load(Ordering::Relaxed)
+    }
+
+    pub fn udp_sessions(&self) -> UdpSessions {
+        UdpSessions {
+            clients: self.client_udp_sessions.iter().map(|e| (*e.key(), e.value().upstream)).collect(),
+            players: self.player_udp_sessions.iter().map(|e| (*e.key(), e.value().upstream)).collect(),
+        }
+    }
+
+    /// Восстановить сессии из снимка предыдущего процесса. TCP сессии остались у него,
+    /// поэтому владельца у восстановленных нет: они живут, пока используются.
+    pub fn restore_udp_sessions(&self, sessions: UdpSessions) -> usize {
+        let owner = SessionOwner::Orphan(Instant::now());
+        let count = sessions.clients.len() + sessions.players.len();
+        for (client_ip, upstream) in sessions.clients {
+            let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+            self.insert_udp_session(&self.client_udp_sessions, client_ip, upstream, generation, owner, Some(client_ip));
+        }
+        for (player, upstream) in sessions.players {
+            let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+            self.insert_udp_session(&self.player_udp_sessions, player, upstream, generation, owner, None);
         }
+        count
+    }
+
+    /// Удалить UDP сессии, освобождённые TCP сессией дольше grace назад, и сессии без
+    /// владельца, не использованные дольше ttl; возвращает число удалённых
+    pub fn expire_udp_sessions(&self, ttl: Duration, grace: Duration) -> usize {
+        let now = Instant::now();
+        let alive = |session: &UdpSession| match session.owner {
+            SessionOwner::Session => true,
+            SessionOwner::Released(at) => now.duration_since(at) < grace,
+            SessionOwner::Orphan(used) => now.duration_since(used) < ttl,
+        };
+        // Считаем удалённые в retain: сессии добавляются параллельно, разница len() неверна
+        let mut removed = 0;
+        let mut retain = |session: &mut UdpSession| {
+            let keep = alive(session);
+            if !keep {
+                removed += 1;
+            }
+            keep
+        };
+        self.client_udp_sessions.retain(|_, session| retain(session));
+        self.player_udp_sessions.retain(|_, session| retain(session));
+        if removed > 0 {
+            self.udp_sessions_epoch.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_secs(30);
+    const TTL: Duration = Duration::from_secs(300);
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
     }
 
-    /// Получить upstream для клиента (точное совпадение по SocketAddr)
-    pub fn lookup_udp_for_client(&self, client: &SocketAddr) -> Option<SocketAddr> {
-        let guard = self.client_udp_map.lock().unwrap();
-        guard.get(client).cloned()
+    #[test]
+    fn dropped_guard_releases_until_grace_expires() {
+        let router = Arc::new(Router::new());
+        let player = Uuid::from_u128(1);
+        let guard = router.claim_udp_session(Some(ip("192.0.2.1")), Some(player), addr("10.0.0.1:24454"));
+
+        drop(guard);
+        // Освобождённая сессия ещё обслуживает переподключение
+        assert_eq!(router.expire_udp_sessions(TTL, GRACE), 0);
+        assert_eq!(router.lookup_udp_session(&ip("192.0.2.1")), Some(addr("10.0.0.1:24454")));
+        assert_eq!(router.lookup_player_udp_session(&player), Some(addr("10.0.0.1:24454")));
+
+        let epoch = router.udp_sessions_epoch();
+        assert_eq!(router.expire_udp_sessions(TTL, Duration::ZERO), 2);
+        assert_eq!(router.lookup_udp_session(&ip("192.0.2.1")), None);
+        assert_eq!(router.lookup_player_udp_session(&player), None);
+        assert_ne!(router.udp_sessions_epoch(), epoch);
     }
 
-    /// Получить всех клиентов, у которых upstream == server_addr
-    pub fn clients_for_upstream(&self, server_addr: &SocketAddr) -> Vec<SocketAddr> {
-        let guard = self.client_udp_map.lock().unwrap();
-        guard.iter()
-            .filter_map(|(client, up)| if up == server_addr { Some(*client) } else { None })
-            .collect()
+    #[test]
+    fn live_guard_keeps_sessions_past_grace() {
+        let router = Arc::new(Router::new());
+        let _guard = router.claim_udp_session(Some(ip("192.0.2.1")), None, addr("10.0.0.1:24454"));
+        assert_eq!(router.expire_udp_sessions(Duration::ZERO, Duration::ZERO), 0);
+        assert!(router.lookup_udp_session(&ip("192.0.2.1")).is_some());
     }
 
-    /// Регистрация сопоставления по IP (без портов): client_ip -> upstream_ip
-    pub fn register_udp_ip_mapping(&self, client_ip: IpAddr, upstream_ip: IpAddr) {
-        let mut guard = self.client_upstream_ip_map.lock().unwrap();
-        guard.insert(client_ip, upstream_ip);
-        println!("REGISTER UDP IP mapping: {} -> {}", client_ip, upstream_ip);
+    #[test]
+    fn older_guard_does_not_release_newer_claim() {
+        let router = Arc::new(Router::new());
+        let player = Uuid::from_u128(1);
+        let old = router.claim_udp_session(Some(ip("192.0.2.1")), Some(player), addr("10.0.0.1:24454"));
+        let _new = router.claim_udp_session(Some(ip("192.0.2.1")), Some(player), addr("10.0.0.1:24454"));
+
+        drop(old);
+        assert_eq!(router.expire_udp_sessions(TTL, Duration::ZERO), 0);
+        assert_eq!(router.lookup_player_udp_session(&player), Some(addr("10.0.0.1:24454")));
     }
 
-    /// Получить upstream IP для client_ip (без портов)
-    pub fn lookup_udp_ip_for_client(&self, client_ip: &IpAddr) -> Option<IpAddr> {
-        let guard = self.client_upstream_ip_map.lock().unwrap();
-        guard.get(client_ip).cloned()
+    #[test]
+    fn player_switching_subdomain_is_rerouted() {
+        let router = Arc::new(Router::new());
+        let player = Uuid::from_u128(1);
+        let lobby = router.claim_udp_session(Some(ip("192.0.2.1")), Some(player), addr("10.0.0.1:24454"));
+        let epoch = router.udp_sessions_epoch();
+
+        let _survival = router.claim_udp_session(Some(ip("192.0.2.1")), Some(player), addr("10.0.0.2:24454"));
+        // Смена upstream двигает epoch: открытые потоки к старому upstream перепроверяются
+        assert_ne!(router.udp_sessions_epoch(), epoch);
+        assert!(!router.udp_session_is(&ip("192.0.2.1"), Some(&player), addr("10.0.0.1:24454")));
+        assert!(router.udp_session_is(&ip("192.0.2.1"), Some(&player), addr("10.0.0.2:24454")));
+
+        // Завершение прежней TCP сессии новую не трогает
+        drop(lobby);
+        assert_eq!(router.expire_udp_sessions(TTL, Duration::ZERO), 0);
+        assert_eq!(router.lookup_udp_session(&ip("192.0.2.1")), Some(addr("10.0.0.2:24454")));
+        assert_eq!(router.lookup_player_udp_session(&player), Some(addr("10.0.0.2:24454")));
+    }
+
+    fn route(name: &str, tcp: &str, udp: Option<&str>) -> Route {
+        Route::new(name.to_string(), addr(tcp), udp.map(addr))
+    }
+
+    #[test]
+    fn replace_routes_swaps_whole_table() {
+        let router = Router::new();
+        router.add_route(route("Lobby.example.com", "10.0.0.1:25565", Some("10.0.0.1:24454")));
+        router.add_route(route("old.example.com", "10.0.0.9:25565", None));
+        // Повторное добавление под тем же именем в другом регистре заменяет маршрут
+        router.add_route(route("lobby.EXAMPLE.com", "10.0.0.2:25565", None));
+        assert_eq!(router.route_count(), 2);
+        assert_eq!(router.lookup_route("lobby.example.com").unwrap().tcp, addr("10.0.0.2:25565"));
+        assert!(router.route_for_upstream(&addr("10.0.0.1:24454")).is_none());
+
+        router.replace_routes(vec![
+            route("lobby.example.com", "10.0.0.1:25565", Some("10.0.0.3:24454")),
+            route("survival.example.com", "10.0.0.1:25566", None),
+        ]);
+        assert_eq!(router.route_count(), 2);
+        assert!(router.lookup_route("old.example.com").is_none());
+        assert!(router.routes_for_upstream_ip(&ip("10.0.0.9")).is_empty());
+        assert_eq!(router.lookup_route("survival.example.com").unwrap().tcp, addr("10.0.0.1:25566"));
+    }
+
+    #[test]
+    fn upstream_indexes_cover_tcp_and_udp() {
+        let router = Router::new();
+        router.replace_routes(vec![
+            route("lobby.example.com", "10.0.0.1:25565", Some("10.0.0.3:24454")),
+            route("survival.example.com", "10.0.0.1:25566", None),
+        ]);
+        let name = |addr: &str| router.route_for_upstream(&self::addr(addr)).map(|r| r.name.clone());
+        assert_eq!(name("10.0.0.1:25565").as_deref(), Some("lobby.example.com"));
+        assert_eq!(name("10.0.0.3:24454").as_deref(), Some("lobby.example.com"));
+        assert_eq!(name("10.0.0.1:25566").as_deref(), Some("survival.example.com"));
+        assert_eq!(name("10.0.0.1:24454"), None);
+
+        let mut names: Vec<_> = router.routes_for_upstream_ip(&ip("10.0.0.1")).iter().map(|r| r.name.clone()).collect();
+        names.sort();
+        assert_eq!(names, ["lobby.example.com", "survival.example.com"]);
+        assert_eq!(router.routes_for_upstream_ip(&ip("10.0.0.3")).len(), 1);
+    }
+
+    #[test]
+    fn sessions_are_found_by_client_ip_and_player() {
+        let router = Arc::new(Router::new());
+        let alex = Uuid::from_u128(1);
+        let steve = Uuid::from_u128(2);
+        // Два игрока за одним NAT на разных серверах
+        let _alex = router.claim_udp_session(Some(ip("::ffff:192.0.2.1")), Some(alex), addr("10.0.0.1:24454"));
+        let _steve = router.claim_udp_session(Some(ip("192.0.2.1")), Some(steve), addr("10.0.0.2:24454"));
+
+        // IPv4-mapped адрес приводится к IPv4; по IP побеждает последняя регистрация
+        assert_eq!(router.lookup_udp_session(&ip("192.0.2.1")), Some(addr("10.0.0.2:24454")));
+        assert_eq!(router.lookup_player_udp_session(&alex), Some(addr("10.0.0.1:24454")));
+        assert_eq!(router.lookup_player_udp_session(&steve), Some(addr("10.0.0.2:24454")));
+        assert_eq!(router.lookup_player_udp_session(&Uuid::from_u128(3)), None);
+        assert_eq!(router.lookup_udp_session(&ip("192.0.2.2")), None);
+
+        assert!(router.player_udp_session_from(&alex, &ip("192.0.2.1")));
+        assert!(!router.player_udp_session_from(&alex, &ip("192.0.2.2")));
+    }
+
+    #[test]
+    fn restored_sessions_live_while_used() {
+        let router = Arc::new(Router::new());
+        let player = Uuid::from_u128(1);
+        let _guard = router.claim_udp_session(Some(ip("192.0.2.1")), Some(player), addr("10.0.0.1:24454"));
+
+        let restored = Router::new();
+        assert_eq!(restored.restore_udp_sessions(router.udp_sessions()), 2);
+        assert_eq!(restored.lookup_udp_session(&ip("192.0.2.1")), Some(addr("10.0.0.1:24454")));
+        assert_eq!(restored.lookup_player_udp_session(&player), Some(addr("10.0.0.1:24454")));
+        // Владелец остался у прежнего процесса: UUID не привязан к IP
+        assert!(!restored.player_udp_session_from(&player, &ip("192.0.2.1")));
+
+        assert_eq!(restored.expire_udp_sessions(TTL, Duration::ZERO), 0);
+        assert_eq!(restored.expire_udp_sessions(Duration::ZERO, GRACE), 2);
     }
 }
@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+
+#[cfg(target_os = "linux")]
+use crate::proto::transparent;
+
+/// Параметры TCP сокета: `listeners.socket` — для клиентских подключений,
+/// `options.socket` маршрута — для подключений к upstream.
+/// Незаданные поля оставляют значения системы.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SocketOptions {
+    /// Через сколько секунд простоя слать keepalive пробы (любое поле keepalive_* включает keepalive)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive_secs: Option<u64>,
+    /// Интервал между пробами
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive_interval_secs: Option<u64>,
+    /// Число неотвеченных проб до разрыва
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive_retries: Option<u32>,
+    /// SO_SNDBUF, байт
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_buffer: Option<usize>,
+    /// SO_RCVBUF, байт
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_buffer: Option<usize>,
+    /// TCP_USER_TIMEOUT: разрыв, если отправленные данные не подтверждены дольше этого (Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_timeout_ms: Option<u64>,
+    /// Байт IP TOS (IPv6 — traffic class) целиком
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tos: Option<u8>,
+    /// DSCP (0–63): то же, что tos = dscp << 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+    /// SO_MARK для маршрутизации по политикам (Linux, нужен CAP_NET_ADMIN)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mark: Option<u32>,
+    /// Локальный адрес исходящих подключений к upstream (только у маршрутов)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind: Option<IpAddr>,
+}
+
+impl SocketOptions {
+    /// Параметры поверх значений по умолчанию (каждое незаданное поле берётся из defaults)
+    pub fn or(self, defaults: &SocketOptions) -> SocketOptions {
+        // tos и dscp задают одно и то же: заданное у маршрута заменяет оба значения по умолчанию
+        let (tos, dscp) = match (self.tos, self.dscp) {
+            (None, None) => (defaults.tos, defaults.dscp),
+            own => own,
+        };
+        SocketOptions {
+            keepalive_secs: self.keepalive_secs.or(defaults.keepalive_secs),
+            keepalive_interval_secs: self.keepalive_interval_secs.or(defaults.keepalive_interval_secs),
+            keepalive_retries: self.keepalive_retries.or(defaults.keepalive_retries),
+            send_buffer: self.send_buffer.or(defaults.send_buffer),
+            recv_buffer: self.recv_buffer.or(defaults.recv_buffer),
+            user_timeout_ms: self.user_timeout_ms.or(defaults.user_timeout_ms),
+            tos,
+            dscp,
+            mark: self.mark.or(defaults.mark),
+            bind: self.bind.or(defaults.bind),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |name: &str, v: Option<u64>| match v {
+            Some(0) => Err(format!("socket.{}: значение должно быть больше 0", name)),
+            _ => Ok(()),
+        };
+        positive("keepalive_secs", self.keepalive_secs)?;
+        positive("keepalive_interval_secs", self.keepalive_interval_secs)?;
+        positive("keepalive_retries", self.keepalive_retries.map(u64::from))?;
+        positive("send_buffer", self.send_buffer.map(|v| v as u64))?;
+        positive("recv_buffer", self.recv_buffer.map(|v| v as u64))?;
+        positive("user_timeout_ms", self.user_timeout_ms)?;
+        if self.tos.is_some() && self.dscp.is_some() {
+            return Err("socket: tos и dscp задают одно и то же, укажите что-то одно".to_string());
+        }
+        if let Some(dscp) = self.dscp && dscp > 63 {
+            return Err(format!("socket.dscp: {} вне диапазона 0–63", dscp));
+        }
+        if let Some(bind) = self.bind && (bind.is_multicast() || bind.is_unspecified()) {
+            return Err(format!("socket.bind: {} не может быть локальным адресом подключения", bind));
+        }
+        Ok(())
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Байт TOS / traffic class
+    fn tos_byte(&self) -> Option<u32> {
+        self.tos.map(u32::from).or(self.dscp.map(|dscp| u32::from(dscp) << 2))
+    }
+
+    /// Применить параметры к сокету (кроме bind). `v6` — сокет IPv6
+    pub fn apply(&self, socket: SockRef<'_>, v6: bool) -> io::Result<()> {
+        if self.keepalive_secs.is_some() || self.keepalive_interval_secs.is_some() || self.keepalive_retries.is_some() {
+            let mut keepalive = TcpKeepalive::new();
+            if let Some(secs) = self.keepalive_secs {
+                keepalive = keepalive.with_time(Duration::from_secs(secs));
+            }
+            #[cfg(target_os = "linux")]
+            {
+                if let Some(secs) = self.keepalive_interval_secs {
+                    keepalive = keepalive.with_interval(Duration::from_secs(secs));
+                }
+                if let Some(retries) = self.keepalive_retries {
+                    keepalive = keepalive.with_retries(retries);
+                }
+            }
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(ms) = self.user_timeout_ms {
+            #[cfg(target_os = "linux")]
+            socket.set_tcp_user_timeout(Some(Duration::from_millis(ms)))?;
+            #[cfg(not(target_os = "linux"))]
+            return Err(unsupported("user_timeout_ms", ms));
+        }
+        if let Some(tos) = self.tos_byte() {
+            #[cfg(target_os = "linux")]
+            if v6 {
+                socket.set_tclass_v6(tos)?;
+            } else {
+                socket.set_tos_v4(tos)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            if v6 {
+                warn_tclass_unsupported();
+            } else {
+                socket.set_tos_v4(tos)?;
+            }
+        }
+        if let Some(mark) = self.mark {
+            #[cfg(target_os = "linux")]
+            socket.set_mark(mark)?;
+            #[cfg(not(target_os = "linux"))]
+            return Err(unsupported("mark", mark));
+        }
+        Ok(())
+    }
+
+    /// Применить параметры к принятому клиентскому подключению
+    pub fn apply_stream(&self, stream: &TcpStream) -> io::Result<()> {
+        self.apply(SockRef::from(stream), stream.local_addr()?.is_ipv6())
+    }
+
+    /// Подключиться к upstream: параметры и локальный адрес применяются до connect.
+    /// `client` — адрес клиента для прозрачного режима: подключение идёт с него
+    pub async fn connect(&self, addr: SocketAddr, client: Option<IpAddr>) -> io::Result<TcpStream> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        self.apply(SockRef::from(&socket), addr.is_ipv6())?;
+        match client {
+            #[cfg(target_os = "linux")]
+            Some(client) => {
+                let source = transparent::source_ip(client, addr)?;
+                transparent::set_transparent(SockRef::from(&socket), addr.is_ipv6())?;
+                socket.bind(SocketAddr::new(source, 0))?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(client) => return Err(unsupported("transparent", client)),
+            None => {
+                if let Some(bind) = self.bind {
+                    socket.bind(SocketAddr::new(bind, 0))?;
+                }
+            }
+        }
+        socket.connect(addr).await
+    }
+}
+
+/// Traffic class IPv6 socket2 задаёт только в Linux: предупреждаем один раз, а не на каждое подключение
+#[cfg(not(target_os = "linux"))]
+fn warn_tclass_unsupported() {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    WARNED.call_once(|| eprintln!("\x1b[33msocket.tos/dscp: traffic class IPv6 поддерживается только в Linux, IPv6 подключения идут без него\x1b[0m"));
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported(name: &str, value: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("socket.{} = {}: поддерживается только в Linux", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::{Domain, Socket, Type};
+
+    fn tcp_socket(domain: Domain) -> Socket {
+        Socket::new(domain, Type::STREAM, None).unwrap()
+    }
+
+    #[test]
+    fn applied_options_read_back() {
+        let options = SocketOptions {
+            keepalive_secs: Some(60),
+            keepalive_interval_secs: Some(10),
+            keepalive_retries: Some(4),
+            send_buffer: Some(64 * 1024),
+            recv_buffer: Some(128 * 1024),
+            user_timeout_ms: Some(30_000),
+            dscp: Some(46),
+            ..SocketOptions::default()
+        };
+        let socket = tcp_socket(Domain::IPV4);
+        options.apply(SockRef::from(&socket), false).unwrap();
+
+        assert!(socket.keepalive().unwrap());
+        // Ядро может увеличить буферы (Linux удваивает), но не уменьшить
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.recv_buffer_size().unwrap() >= 128 * 1024);
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(socket.tcp_keepalive_time().unwrap(), Duration::from_secs(60));
+            assert_eq!(socket.tcp_keepalive_interval().unwrap(), Duration::from_secs(10));
+            assert_eq!(socket.tcp_keepalive_retries().unwrap(), 4);
+            assert_eq!(socket.tcp_user_timeout().unwrap(), Some(Duration::from_millis(30_000)));
+        }
+        assert_eq!(socket.tos_v4().unwrap(), 46 << 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn traffic_class_applies_to_ipv6() {
+        let Ok(socket) = Socket::new(Domain::IPV6, Type::STREAM, None) else { return };
+        let options = SocketOptions { tos: Some(0xb8), ..SocketOptions::default() };
+        options.apply(SockRef::from(&socket), true).unwrap();
+        assert_eq!(socket.tclass_v6().unwrap(), 0xb8);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mark_needs_cap_net_admin() {
+        let socket = tcp_socket(Domain::IPV4);
+        let options = SocketOptions { mark: Some(100), ..SocketOptions::default() };
+        match options.apply(SockRef::from(&socket), false) {
+            Ok(()) => assert_eq!(socket.mark().unwrap(), 100),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_applies_options_and_bind() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SocketOptions {
+            keepalive_secs: Some(30),
+            tos: Some(0x10),
+            bind: Some("127.0.0.1".parse().unwrap()),
+            ..SocketOptions::default()
+        };
+        let stream = options.connect(listener.local_addr().unwrap(), None).await.unwrap();
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.tos_v4().unwrap(), 0x10);
+        assert_eq!(stream.local_addr().unwrap().ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn route_options_override_defaults_field_by_field() {
+        let defaults = SocketOptions { keepalive_secs: Some(60), dscp: Some(46), mark: Some(1), ..SocketOptions::default() };
+        let own = SocketOptions { tos: Some(0x10), mark: Some(2), ..SocketOptions::default() };
+        let merged = own.or(&defaults);
+        assert_eq!(merged.keepalive_secs, Some(60));
+        assert_eq!(merged.mark, Some(2));
+        // tos маршрута заменяет dscp по умолчанию
+        assert_eq!((merged.tos, merged.dscp), (Some(0x10), None));
+        assert_eq!(merged.tos_byte(), Some(0x10));
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        let invalid = [
+            SocketOptions { keepalive_secs: Some(0), ..SocketOptions::default() },
+            SocketOptions { send_buffer: Some(0), ..SocketOptions::default() },
+            SocketOptions { tos: Some(1), dscp: Some(1), ..SocketOptions::default() },
+            SocketOptions { dscp: Some(64), ..SocketOptions::default() },
+            SocketOptions { bind: Some("0.0.0.0".parse().unwrap()), ..SocketOptions::default() },
+            SocketOptions { bind: Some("224.0.0.1".parse().unwrap()), ..SocketOptions::default() },
+        ];
+        for options in invalid {
+            assert!(options.validate().is_err(), "{:?}", options);
+        }
+        assert!(SocketOptions { dscp: Some(63), ..SocketOptions::default() }.validate().is_ok());
+    }
+}
@@ -0,0 +1,187 @@
+//! Пересылка TCP без копирования через userspace (только Linux): данные идут
+//! сокет -> pipe -> сокет через splice(2) и остаются в ядре.
+use tokio::io::{AsyncWriteExt, Interest};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::time::{timeout, Duration};
+use std::io::{self, Result};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+
+use crate::proto::RateLimiter;
+use crate::proto::forward::Activity;
+use crate::consts::SPLICE_PIPE_SIZE;
+
+/// Неблокирующий pipe — промежуточный буфер splice в ядре
+pub struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl Pipe {
+    pub fn new() -> Result<Self> {
+        let mut fds = [0; 2];
+        // SAFETY: pipe2 заполняет массив из двух дескрипторов
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: дескрипторы только что созданы и больше никому не принадлежат
+        let pipe = unsafe { Self { read: OwnedFd::from_raw_fd(fds[0]), write: OwnedFd::from_raw_fd(fds[1]) } };
+        // Не критично: при отказе остаётся размер по умолчанию (64 КиБ)
+        // SAFETY: fcntl над собственным дескриптором
+        unsafe { libc::fcntl(pipe.write.as_raw_fd(), libc::F_SETPIPE_SZ, SPLICE_PIPE_SIZE as libc::c_int) };
+        Ok(pipe)
+    }
+}
+
+fn splice(from: RawFd, to: RawFd, len: usize) -> Result<usize> {
+    // SAFETY: оба дескриптора живы на время вызова; смещения не нужны (сокет и pipe)
+    let n = unsafe {
+        libc::splice(from, ptr::null_mut(), to, ptr::null_mut(), len, libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK)
+    };
+    if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+}
+
+/// То же, что `forward`, но через splice: ограничение скорости, таймаут простоя и подсчёт
+/// байт сохраняются. Возвращает число переданных байт.
+pub async fn forward_splice(
+    reader: &OwnedReadHalf,
+    writer: &mut OwnedWriteHalf,
+    pipe: Pipe,
+    mut limiter: Option<RateLimiter>,
+    idle: Option<Duration>,
+    activity: &Activity,
+) -> Result<u64> {
+    let from = reader.as_ref();
+    let mut total = 0u64;
+    loop {
+        // Забираем из сокета не больше, чем лимитер способен выдать за раз
+        let max = limiter.as_ref().map_or(SPLICE_PIPE_SIZE, |l| l.capacity().clamp(1, SPLICE_PIPE_SIZE));
+        let n = loop {
+            match idle {
+                Some(idle) => {
+                    let remaining = idle.saturating_sub(activity.idle());
+                    if remaining.is_zero() {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "Превышено время простоя сессии"));
+                    }
+                    match timeout(remaining, from.readable()).await {
+                        Ok(res) => res?,
+                        Err(_) => continue,
+                    }
+                }
+                None => from.readable().await?,
+            }
+            match from.try_io(Interest::READABLE, || splice(from.as_raw_fd(), pipe.write.as_raw_fd(), max)) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if n == 0 {
+            break;
+        }
+        activity.touch();
+        if let Some(limiter) = limiter.as_mut() {
+            limiter.acquire(n).await;
+        }
+
+        // Pipe опустошается полностью, прежде чем снова читать из сокета
+        let to = writer.as_ref();
+        let mut pending = n;
+        while pending > 0 {
+            to.writable().await?;
+            match to.try_io(Interest::WRITABLE, || splice(pipe.read.as_raw_fd(), to.as_raw_fd(), pending)) {
+                Ok(sent) => pending -= sent,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        total += n as u64;
+    }
+    writer.shutdown().await?;
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::proto::forward::forward;
+
+    /// Соединённая пара сокетов на loopback
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    fn payload() -> Vec<u8> {
+        // Больше нескольких размеров pipe, чтобы данные шли в несколько проходов
+        (0..3 * SPLICE_PIPE_SIZE + 12_345).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    /// Прогнать payload клиент -> прокси -> сервер; возвращает принятое сервером и счётчик прокси
+    async fn relay(use_splice: bool, limiter: Option<RateLimiter>) -> (Vec<u8>, u64) {
+        let (mut client, inbound) = pair().await;
+        let (outbound, mut server) = pair().await;
+        let (mut ri, _wi) = inbound.into_split();
+        let (_ro, mut wo) = outbound.into_split();
+
+        let data = payload();
+        let send = async move {
+            client.write_all(&data).await.unwrap();
+            client.shutdown().await.unwrap();
+            client
+        };
+        let proxy = async {
+            let activity = Activity::new();
+            match use_splice {
+                true => forward_splice(&ri, &mut wo, Pipe::new().unwrap(), limiter, None, &activity).await.unwrap(),
+                false => forward(&mut ri, &mut wo, limiter, None, &activity).await.unwrap(),
+            }
+        };
+        let receive = async {
+            let mut received = Vec::new();
+            server.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let (_client, total, received) = tokio::join!(send, proxy, receive);
+        (received, total)
+    }
+
+    #[tokio::test]
+    async fn splice_relays_bytes_like_forward() {
+        let (spliced, spliced_total) = relay(true, None).await;
+        let (copied, copied_total) = relay(false, None).await;
+        assert_eq!(spliced, payload());
+        assert_eq!(spliced_total, payload().len() as u64);
+        assert_eq!((spliced, spliced_total), (copied, copied_total));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn splice_respects_rate_limit() {
+        let started = tokio::time::Instant::now();
+        let (received, total) = relay(true, Some(RateLimiter::new(64 * 1024, 16 * 1024))).await;
+        assert_eq!(received, payload());
+        assert_eq!(total, payload().len() as u64);
+        // Сверх запаса burst данные идут не быстрее bytes_per_sec
+        let expected = Duration::from_secs_f64((payload().len() - 16 * 1024) as f64 / (64 * 1024) as f64);
+        assert!(started.elapsed() >= expected, "{:?} < {:?}", started.elapsed(), expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn splice_times_out_when_idle() {
+        let (_client, inbound) = pair().await;
+        let (outbound, _server) = pair().await;
+        let (ri, _wi) = inbound.into_split();
+        let (_ro, mut wo) = outbound.into_split();
+
+        let activity = Activity::new();
+        let started = tokio::time::Instant::now();
+        let err = forward_splice(&ri, &mut wo, Pipe::new().unwrap(), None, Some(Duration::from_secs(30)), &activity).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() >= Duration::from_secs(30));
+    }
+}
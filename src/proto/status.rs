@@ -0,0 +1,53 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+use std::io::Result;
+
+use crate::proto::VarInt;
+use crate::proto::varint::write_varint_string;
+use crate::proto::packet::{read_packet, write_packet};
+
+const STATUS_REQUEST: i32 = 0x00;
+const STATUS_RESPONSE: i32 = 0x00;
+const PING_REQUEST: i32 = 0x01;
+const PONG_RESPONSE: i32 = 0x01;
+const LOGIN_DISCONNECT: i32 = 0x00;
+
+/// Ответить клиенту в состоянии status собственным MOTD (сервер недоступен):
+/// Status Request -> Status Response, затем Ping -> Pong.
+pub async fn serve_status<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, protocol_version: i32, motd: &str) -> Result<()> {
+    let mut raw = Vec::new();
+    let body = read_packet(stream, &mut raw).await?;
+    let mut slice: &[u8] = &body;
+    if VarInt::read_from_slice(&mut slice)? != STATUS_REQUEST {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "ожидался Status Request"));
+    }
+
+    let status = serde_json::json!({
+        "version": { "name": "offline", "protocol": protocol_version },
+        "players": { "max": 0, "online": 0 },
+        "description": { "text": motd },
+    });
+    let mut packet = Vec::new();
+    VarInt::write(STATUS_RESPONSE, &mut packet);
+    write_varint_string(&status.to_string(), &mut packet);
+    write_packet(stream, &packet).await?;
+
+    // Ping необязателен: клиент может закрыть соединение сразу после ответа
+    raw.clear();
+    let Ok(body) = read_packet(stream, &mut raw).await else { return Ok(()) };
+    let mut slice: &[u8] = &body;
+    if VarInt::read_from_slice(&mut slice)? == PING_REQUEST {
+        let mut packet = Vec::with_capacity(9);
+        VarInt::write(PONG_RESPONSE, &mut packet);
+        packet.extend_from_slice(slice);
+        write_packet(stream, &packet).await?;
+    }
+    Ok(())
+}
+
+/// Отключить клиента в состоянии login с текстом причины
+pub async fn login_disconnect<S: AsyncWrite + Unpin>(stream: &mut S, reason: &str) -> Result<()> {
+    let mut packet = Vec::new();
+    VarInt::write(LOGIN_DISCONNECT, &mut packet);
+    write_varint_string(&serde_json::json!({ "text": reason }).to_string(), &mut packet);
+    write_packet(stream, &packet).await
+}
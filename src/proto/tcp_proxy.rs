@@ -1,49 +1,57 @@
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::time::timeout;
-use bytes::BytesMut;
 use std::sync::Arc;
 use std::io::Result;
-use std::net::SocketAddr;
 
-use crate::proto::{Router, RateLimiter, VarInt, read_varint_string_from_slice};
+use crate::proto::{Router, RateLimiter, Handshake, LoginStart, Route};
+use crate::proto::{packet, proxy_protocol, status};
+use crate::proto::route::IpForwarding;
+use crate::proto::forward::{forward, Activity};
+use crate::proto::frontend::Frontend;
+#[cfg(target_os = "linux")]
+use crate::proto::splice::{forward_splice, Pipe};
 use crate::consts::{
     DEFAULT_BYTES_PER_SEC,
     DEFAULT_BURST_BYTES,
     HANDSHAKE_READ_TIMEOUT,
-    MAX_PACKET_LEN
+    UPSTREAM_CONNECT_TIMEOUT
 };
 
 pub struct TcpProxy {
     inbound: TcpStream,
     router: Arc<Router>,
     rl: RateLimiter,
+    frontend: Option<Arc<Frontend>>,
 }
 
 impl TcpProxy {
     pub fn new(inbound: TcpStream, router: Arc<Router>) -> Self {
         let rl = RateLimiter::new(DEFAULT_BYTES_PER_SEC, DEFAULT_BURST_BYTES);
-        Self { inbound, router, rl }
+        Self { inbound, router, rl, frontend: None }
+    }
+
+    /// Подключения приходят через фронтовой сервис, передающий адрес игрока в Handshake
+    pub fn with_frontend(mut self, frontend: Option<Arc<Frontend>>) -> Self {
+        self.frontend = frontend;
+        self
     }
 
     pub async fn run(mut self) -> Result<()> {
         let _ = self.inbound.set_nodelay(true);
 
         // Получаем peer_addr до дальнейших действий, чтобы корректно логировать попытку подключения
-        let client_addr = match self.inbound.peer_addr() {
-            Ok(a) => Some(a),
-            Err(_) => None,
-        };
-        let client_str = client_addr
+        let mut client_addr = self.inbound.peer_addr().ok();
+        let mut client_str = client_addr
             .map(|a| a.to_string())
             .unwrap_or_else(|| "<unknown>".to_string());
 
         // Лог: попытка подключения
         println!("{} запрашивает соединение", client_str);
 
-        // Read first packet with timeout
-        let (full_packet, maybe_server_name) =
-            match timeout(HANDSHAKE_READ_TIMEOUT, self.read_first_packet_and_parse_servername()).await {
+        // Read handshake (and Login Start for login connections) with timeout
+        let (full_packet, handshake_len, mut handshake, login) =
+            match timeout(HANDSHAKE_READ_TIMEOUT, self.read_handshake()).await {
                 Ok(Ok(v)) => v,
                 Ok(Err(e)) => {
                     let _ = self.inbound.shutdown().await;
@@ -55,24 +63,35 @@ impl TcpProxy {
                 }
             };
 
-        if full_packet.is_empty() {
-            let _ = self.inbound.shutdown().await;
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Отправлен пустой пакет"));
+        // Адрес игрока от фронта: дальше (журнал, списки доступа, UDP сессия, PROXY protocol,
+        // forwarding) используется он, а upstream получает адрес сервера без данных фронта
+        let mut decoded = false;
+        if let Some(frontend) = &self.frontend {
+            match frontend.decode(&handshake.server_address) {
+                Ok(Some(d)) => {
+                    client_str = format!("{} (через {})", d.client, client_str);
+                    println!("{} передан фронтом", client_str);
+                    handshake.server_address = d.host;
+                    client_addr = Some(d.client);
+                    decoded = true;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = self.inbound.shutdown().await;
+                    return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{}: {}", client_str, e)));
+                }
+            }
         }
 
-        let maybe_server_name = match maybe_server_name {
-            Some(s) => s,
-            None => {
-                let _ = self.inbound.shutdown().await;
-                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Не удалось получить имя сервера"));
-            }
-        };
+        // Метки FML, порт, завершающая точка и регистр на выбор маршрута не влияют;
+        // upstream получает адрес сервера с метками, как его прислал клиент
+        let server_host = handshake.server_host();
 
-        let server_name = match maybe_server_name.split('.').next() {
-            Some(s) => s.to_string(),
+        let server_name = match handshake.route_name() {
+            Some(s) => s,
             None => {
                 let _ = self.inbound.shutdown().await;
-                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Неверное имя сервера '{}'", maybe_server_name)));
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Неверное имя сервера '{}'", handshake.server_address.escape_debug())));
             }
         };
 
@@ -81,138 +100,161 @@ impl TcpProxy {
             Some(r) => r,
             None => {
                 let _ = self.inbound.shutdown().await;
-                return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Неизхвестное имя сервера '{}'", maybe_server_name)));
+                return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Неизхвестное имя сервера '{}'", server_host)));
             }
         };
 
-        let upstream_tcp = route.tcp.clone();
-        let upstream_udp = route.udp.clone();
-
-        // Получаем peer_addr до into_split()
-        let client_addr = match self.inbound.peer_addr() {
-            Ok(a) => Some(a),
-            Err(_) => None,
-        };
-
-        // Регистрируем IP->IP сопоставление (client_ip -> upstream_ip) для UDP
-        if let (Some(client), Ok(up_addr)) = (client_addr, upstream_udp.parse::<SocketAddr>()) {
-            let client_ip = client.ip();
-            let upstream_ip = up_addr.ip();
-            self.router.register_udp_ip_mapping(client_ip, upstream_ip);
-            println!("REGISTER UDP IP mapping (from TCP handshake): {} -> {}", client_ip, upstream_ip);
+        // Списки доступа маршрута
+        if let Some(client) = client_addr && !route.options.access.permits(&client.ip()) {
+            let _ = self.inbound.shutdown().await;
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{}: доступ к '{}' запрещён", client_str, route.name)));
         }
 
+        // Регистрируем UDP сессию (client_ip и UUID игрока -> upstream udp маршрута) для UDP прокси.
+        // UUID — тот, под которым игрока знает backend. Сессия принадлежит этой TCP сессии
+        // и освобождается при её завершении
+        let _udp_session = route.udp.map(|udp| {
+            self.router.claim_udp_session(client_addr.map(|c| c.ip()), login.as_ref().map(|l| route.options.backend_player_uuid(l)), udp)
+        });
+
         // Подключаемся к upstream по TCP
-        let mut outbound = TcpStream::connect(upstream_tcp).await?;
+        let connect_timeout = route.options.timeouts.connect.unwrap_or(UPSTREAM_CONNECT_TIMEOUT);
+        // В прозрачном режиме upstream видит настоящий адрес клиента
+        let source = client_addr.filter(|_| route.options.transparent).map(|c| c.ip());
+        let mut outbound = match timeout(connect_timeout, route.options.socket.connect(route.tcp, source)).await {
+            Ok(Ok(s)) => s,
+            res => {
+                let err = match res {
+                    Ok(Err(e)) => e,
+                    _ => std::io::Error::new(std::io::ErrorKind::TimedOut, "Превышено время подключения к upstream"),
+                };
+                // Upstream недоступен: отвечаем MOTD маршрута, если он задан
+                if let Some(motd) = &route.options.motd {
+                    let _ = self.serve_offline(&handshake, motd).await;
+                }
+                let _ = self.inbound.shutdown().await;
+                return Err(err);
+            }
+        };
         let _ = outbound.set_nodelay(true);
 
         // Лог о подключении
-        println!("{} установил соединение с {}", client_str, maybe_server_name);
+        match &login {
+            Some(login) => println!("{} ({}) установил соединение с {}:{}", client_str, login.name, server_host, handshake.server_port),
+            None => println!("{} установил соединение с {}:{}", client_str, server_host, handshake.server_port),
+        }
 
         if !self.rl.allow(full_packet.len()) {
             let _ = self.inbound.shutdown().await;
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "rate limit exceeded"));
+            return Err(std::io::Error::other("rate limit exceeded"));
         }
 
-        outbound.write_all(&full_packet).await?;
-        outbound.flush().await?;
-
-        // Разделяем потоки и проксируем данные (чистый TCP proxy)
-        let (mut ri, mut wi) = self.inbound.into_split();
-        let (mut ro, mut wo) = outbound.into_split();
-
-        let c2s = async move {
-            io::copy(&mut ri, &mut wo).await?;
-            wo.shutdown().await
+        // Handshake для upstream: подмена адреса и порта сервера, затем BungeeCord forwarding
+        // (IP клиента и offline UUID игрока дописываются в адрес). Login Start уходит как есть
+        let mut upstream_handshake = route.options.rewrite_handshake.as_ref().map(|r| r.apply(&handshake, &route))
+            .or_else(|| decoded.then(|| handshake.clone()));
+        if let (Some(IpForwarding::BungeeCord), Some(client), Some(login)) = (route.options.forwarding, client_addr, &login) {
+            let base = upstream_handshake.as_ref().unwrap_or(&handshake);
+            upstream_handshake = Some(base.with_bungeecord_forwarding(client.ip(), route.options.backend_player_uuid(login)));
+        }
+        let full_packet = match upstream_handshake {
+            Some(upstream_handshake) => {
+                let mut packets = packet::encode_packet(&upstream_handshake.encode());
+                packets.extend_from_slice(&full_packet[handshake_len..]);
+                packets
+            }
+            None => full_packet,
         };
 
-        let s2c = async move {
-            io::copy(&mut ro, &mut wi).await?;
-            wi.shutdown().await
-        };
+        // Заголовок PROXY protocol идёт перед первыми байтами клиента
+        if let Some(version) = route.options.proxy_protocol && let Some(client) = client_addr {
+            let local = self.inbound.local_addr()?;
+            outbound.write_all(&proxy_protocol::header(version, client, local)).await?;
+        }
 
-        // Ждём завершения обеих задач
-        let res = tokio::try_join!(c2s, s2c);
+        outbound.write_all(&full_packet).await?;
+        outbound.flush().await?;
 
-        // Пробрасываем ошибку, если была
-        res.map(|_| ())
+        let (sent, received) = self.proxy(outbound, &route).await?;
+        println!("{} сессия завершена: клиент -> сервер {} байт, сервер -> клиент {} байт", client_str, sent, received);
+        Ok(())
     }
 
-    /// Read one full length-prefixed packet, parse handshake server name if present.
-    async fn read_first_packet_and_parse_servername(&mut self) -> Result<(Vec<u8>, Option<String>)> {
-        // Read up to 5 bytes of varint prefix, appending only newly read bytes
-        let mut len_prefix = BytesMut::with_capacity(5);
-        let mut tmp = [0u8; 5];
-        let mut read_total = 0usize;
-
-        loop {
-            let to_read = 5 - read_total;
-            if to_read == 0 {
-                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "length varint too big"));
-            }
-            let n = self.inbound.read(&mut tmp[read_total..read_total + to_read]).await?;
-            if n == 0 {
-                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stream closed"));
-            }
-
-            // append only newly read bytes
-            len_prefix.extend_from_slice(&tmp[read_total..read_total + n]);
-            read_total += n;
+    /// Проксировать данные в обе стороны с ограничениями маршрута.
+    /// Возвращает число байт (клиент -> сервер, сервер -> клиент)
+    async fn proxy(self, outbound: TcpStream, route: &Route) -> Result<(u64, u64)> {
+        let (mut ri, mut wi) = self.inbound.into_split();
+        let (mut ro, mut wo) = outbound.into_split();
 
-            // scan len_prefix for varint end
-            let mut varint_end = None;
-            for (i, &b) in len_prefix.iter().enumerate() {
-                if b & 0x80 == 0 {
-                    varint_end = Some(i);
-                    break;
+        let limiter = || route.options.rate_limit.map(|rl| RateLimiter::new(rl.bytes_per_sec, rl.burst_bytes));
+        let idle = route.options.timeouts.idle;
+        let activity = Activity::new();
+
+        #[cfg(target_os = "linux")]
+        if route.options.splice {
+            match (Pipe::new(), Pipe::new()) {
+                (Ok(up), Ok(down)) => {
+                    let c2s = forward_splice(&ri, &mut wo, up, limiter(), idle, &activity);
+                    let s2c = forward_splice(&ro, &mut wi, down, limiter(), idle, &activity);
+                    return tokio::try_join!(c2s, s2c);
                 }
+                (Err(e), _) | (_, Err(e)) => eprintln!("splice недоступен ({}), данные копируются обычным способом", e),
             }
+        }
 
-            if let Some(i) = varint_end {
-                // trailing bytes after varint are part of body
-                let trailing = len_prefix.len() - (i + 1);
-                let mut body_extra = Vec::new();
-                if trailing > 0 {
-                    body_extra.extend_from_slice(&len_prefix[i + 1..]);
-                }
+        let c2s = forward(&mut ri, &mut wo, limiter(), idle, &activity);
+        let s2c = forward(&mut ro, &mut wi, limiter(), idle, &activity);
 
-                // parse length from prefix slice
-                let mut tmp_slice: &[u8] = &len_prefix[..=i];
-                let length = VarInt::read_from_slice(&mut tmp_slice)? as usize;
-                if length > MAX_PACKET_LEN {
-                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "packet too large"));
-                }
-
-                // read remaining body (use read_exact into BytesMut to avoid many allocations)
-                let mut body = Vec::with_capacity(length);
-                if !body_extra.is_empty() {
-                    body.extend_from_slice(&body_extra);
-                }
-                while body.len() < length {
-                    let mut chunk = vec![0u8; std::cmp::min(length - body.len(), 8 * 1024)];
-                    self.inbound.read_exact(&mut chunk[..]).await?;
-                    body.extend_from_slice(&chunk);
-                }
+        // Ждём завершения обеих задач и пробрасываем ошибку, если была
+        tokio::try_join!(c2s, s2c)
+    }
 
-                // compose full packet
-                let mut full = Vec::with_capacity(i + 1 + body.len());
-                full.extend_from_slice(&len_prefix[..=i]);
-                full.extend_from_slice(&body);
-
-                // parse server name from body
-                let server_name = {
-                    let mut body_slice: &[u8] = &body;
-                    match VarInt::read_from_slice(&mut body_slice) {
-                        Ok(packet_id) if packet_id == 0 => {
-                            let _ = VarInt::read_from_slice(&mut body_slice).ok();
-                            read_varint_string_from_slice(&mut body_slice).ok()
-                        }
-                        _ => None,
-                    }
-                };
+    /// Не проксировать соединение, а ответить сообщением (например, во время остановки):
+    /// отключение при входе, MOTD на запрос статуса
+    pub async fn refuse(mut self, message: &str) -> Result<()> {
+        let handshake = match timeout(HANDSHAKE_READ_TIMEOUT, self.read_handshake()).await {
+            Ok(Ok((_, _, handshake, _))) => handshake,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Превышено время ожидания рукопожатия")),
+        };
+        self.serve_offline(&handshake, message).await?;
+        self.inbound.shutdown().await
+    }
 
-                return Ok((full, server_name));
-            }
+    /// Ответ клиенту при недоступном upstream: MOTD на запрос статуса, отключение при входе
+    async fn serve_offline(&mut self, handshake: &Handshake, motd: &str) -> Result<()> {
+        if handshake.is_status() {
+            status::serve_status(&mut self.inbound, handshake.protocol_version, motd).await
+        } else if handshake.is_login() {
+            status::login_disconnect(&mut self.inbound, motd).await
+        } else {
+            Ok(())
         }
     }
+
+    /// Read the handshake and, for login connections, the Login Start that follows it.
+    /// Returns raw bytes of both packets (to forward upstream), the length of the raw
+    /// handshake within them and the parsed packets.
+    async fn read_handshake(&mut self) -> Result<(Vec<u8>, usize, Handshake, Option<LoginStart>)> {
+        let mut raw = Vec::new();
+
+        let body = self.read_packet(&mut raw).await?;
+        let handshake = Handshake::decode(&body).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Не удалось получить имя сервера")
+        })?;
+        let handshake_len = raw.len();
+
+        let login = if handshake.is_login() {
+            let body = self.read_packet(&mut raw).await?;
+            LoginStart::decode(&body, handshake.protocol_version).ok()
+        } else {
+            None
+        };
+
+        Ok((raw, handshake_len, handshake, login))
+    }
+
+    async fn read_packet(&mut self, raw: &mut Vec<u8>) -> Result<Vec<u8>> {
+        packet::read_packet(&mut self.inbound, raw).await
+    }
 }
@@ -0,0 +1,54 @@
+//! Прозрачный режим (Linux): подключения к upstream открываются с адреса клиента,
+//! и backend видит настоящие IP игроков без PROXY protocol. Нужны CAP_NET_ADMIN и
+//! маршрутизация, при которой ответы backend на адреса клиентов доставляются на этот
+//! хост локально (пример — scripts/transparent-netns.sh).
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::AsRawFd;
+use tokio::net::UdpSocket;
+
+/// Адрес источника подключения к upstream от имени клиента (IPv4-mapped IPv6 приводится к IPv4)
+pub fn source_ip(client: IpAddr, upstream: SocketAddr) -> io::Result<IpAddr> {
+    let client = client.to_canonical();
+    if client.is_ipv4() != upstream.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("прозрачный режим: адрес клиента {} и upstream {} разных семейств", client, upstream),
+        ));
+    }
+    Ok(client)
+}
+
+/// Разрешить сокету bind на нелокальный адрес (IP_TRANSPARENT и IP_FREEBIND)
+pub fn set_transparent(socket: SockRef<'_>, v6: bool) -> io::Result<()> {
+    if !v6 {
+        socket.set_ip_transparent_v4(true)?;
+        return socket.set_freebind_v4(true);
+    }
+    let on: libc::c_int = 1;
+    // SAFETY: setsockopt читает int по переданному указателю; дескриптор жив на время вызова
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_TRANSPARENT,
+            (&on as *const libc::c_int).cast(),
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    socket.set_freebind_v6(true)
+}
+
+/// UDP сокет с адресом источника `source` (порт выбирает система)
+pub fn bind_udp(source: IpAddr) -> io::Result<UdpSocket> {
+    let addr = SocketAddr::new(source, 0);
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    set_transparent(SockRef::from(&socket), addr.is_ipv6())?;
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
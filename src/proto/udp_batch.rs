@@ -0,0 +1,285 @@
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
+use std::io;
+use std::net::{SocketAddr, Ipv4Addr};
+
+/// Пакетный приём датаграмм: на Linux одним recvmmsg, иначе серией try_recv_from.
+/// Буфер разбит на слоты фиксированного размера; датаграммы больше слота помечаются как обрезанные.
+pub struct RecvBatch {
+    buf: Vec<u8>,
+    slot: usize,
+    lens: Vec<usize>,
+    addrs: Vec<SocketAddr>,
+    truncated: Vec<bool>,
+    count: usize,
+}
+
+impl RecvBatch {
+    pub fn new(batch_size: usize, slot: usize) -> Self {
+        let unspecified = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+        Self {
+            buf: vec![0u8; batch_size * slot],
+            slot,
+            lens: vec![0; batch_size],
+            addrs: vec![unspecified; batch_size],
+            truncated: vec![false; batch_size],
+            count: 0,
+        }
+    }
+
+    /// Дождаться хотя бы одной датаграммы и забрать все готовые (не больше размера пакета)
+    pub async fn recv_from(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        self.count = 0;
+        let n = socket.async_io(Interest::READABLE, || {
+            sys::recv_batch(socket, &mut self.buf, self.slot, &mut self.lens, &mut self.addrs, &mut self.truncated)
+        }).await?;
+        self.count = n;
+        Ok(n)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Датаграмма i: данные, адрес отправителя и признак обрезки
+    pub fn get(&self, i: usize) -> (&[u8], SocketAddr, bool) {
+        let start = i * self.slot;
+        (&self.buf[start..start + self.lens[i]], self.addrs[i], self.truncated[i])
+    }
+}
+
+/// Отправить датаграммы одним вызовом sendmmsg (на Linux). dest = None для подключённого сокета.
+pub async fn send_batch(socket: &UdpSocket, dest: Option<SocketAddr>, datagrams: &[&[u8]]) -> io::Result<()> {
+    let mut sent = 0;
+    while sent < datagrams.len() {
+        sent += socket.async_io(Interest::WRITABLE, || {
+            sys::send_batch(socket, dest, &datagrams[sent..])
+        }).await?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use tokio::net::UdpSocket;
+    use std::io;
+    use std::mem;
+    use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, Ipv4Addr, Ipv6Addr};
+    use std::os::fd::AsRawFd;
+    use std::ptr;
+
+    /// Максимум сообщений за один системный вызов
+    const MAX_BATCH: usize = 64;
+
+    pub fn recv_batch(
+        socket: &UdpSocket,
+        buf: &mut [u8],
+        slot: usize,
+        lens: &mut [usize],
+        addrs: &mut [SocketAddr],
+        truncated: &mut [bool],
+    ) -> io::Result<usize> {
+        let n = lens.len().min(MAX_BATCH);
+        // SAFETY: нулевые значения допустимы для этих C-структур
+        let mut iovs: [libc::iovec; MAX_BATCH] = unsafe { mem::zeroed() };
+        let mut names: [libc::sockaddr_storage; MAX_BATCH] = unsafe { mem::zeroed() };
+        let mut msgs: [libc::mmsghdr; MAX_BATCH] = unsafe { mem::zeroed() };
+
+        let base = buf.as_mut_ptr();
+        for i in 0..n {
+            // SAFETY: buf содержит не меньше lens.len() * slot байт
+            iovs[i].iov_base = unsafe { base.add(i * slot) } as *mut libc::c_void;
+            iovs[i].iov_len = slot;
+            let hdr = &mut msgs[i].msg_hdr;
+            hdr.msg_name = &mut names[i] as *mut libc::sockaddr_storage as *mut libc::c_void;
+            hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            hdr.msg_iov = &mut iovs[i];
+            hdr.msg_iovlen = 1;
+        }
+
+        // SAFETY: все указатели в msgs валидны на время вызова
+        let r = unsafe {
+            libc::recvmmsg(socket.as_raw_fd(), msgs.as_mut_ptr(), n as libc::c_uint, libc::MSG_DONTWAIT, ptr::null_mut())
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let r = r as usize;
+        for i in 0..r {
+            lens[i] = (msgs[i].msg_len as usize).min(slot);
+            truncated[i] = msgs[i].msg_hdr.msg_flags & libc::MSG_TRUNC != 0;
+            addrs[i] = from_sockaddr(&names[i]).unwrap_or(addrs[i]);
+        }
+        Ok(r)
+    }
+
+    pub fn send_batch(socket: &UdpSocket, dest: Option<SocketAddr>, datagrams: &[&[u8]]) -> io::Result<usize> {
+        let n = datagrams.len().min(MAX_BATCH);
+        // SAFETY: нулевые значения допустимы для этих C-структур
+        let mut iovs: [libc::iovec; MAX_BATCH] = unsafe { mem::zeroed() };
+        let mut msgs: [libc::mmsghdr; MAX_BATCH] = unsafe { mem::zeroed() };
+        let mut name = dest.map(to_sockaddr);
+
+        for i in 0..n {
+            iovs[i].iov_base = datagrams[i].as_ptr() as *mut libc::c_void;
+            iovs[i].iov_len = datagrams[i].len();
+            let hdr = &mut msgs[i].msg_hdr;
+            if let Some((storage, len)) = name.as_mut() {
+                hdr.msg_name = storage as *mut libc::sockaddr_storage as *mut libc::c_void;
+                hdr.msg_namelen = *len;
+            }
+            hdr.msg_iov = &mut iovs[i];
+            hdr.msg_iovlen = 1;
+        }
+
+        // SAFETY: все указатели в msgs валидны на время вызова, ядро их только читает
+        let r = unsafe { libc::sendmmsg(socket.as_raw_fd(), msgs.as_mut_ptr(), n as libc::c_uint, libc::MSG_DONTWAIT) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(r as usize)
+    }
+
+    fn from_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // SAFETY: ss_family == AF_INET, значит в storage лежит sockaddr_in
+                let sin = unsafe { &*(storage as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+                Some(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(sin.sin_port))))
+            }
+            libc::AF_INET6 => {
+                // SAFETY: ss_family == AF_INET6, значит в storage лежит sockaddr_in6
+                let sin6 = unsafe { &*(storage as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+                let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+                Some(SocketAddr::V6(SocketAddrV6::new(ip, u16::from_be(sin6.sin6_port), sin6.sin6_flowinfo, sin6.sin6_scope_id)))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_sockaddr(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        // SAFETY: нулевой sockaddr_storage допустим
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(v4) => {
+                // SAFETY: sockaddr_storage достаточно велик для sockaddr_in
+                let sin = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = v4.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*v4.ip()).to_be();
+                mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(v6) => {
+                // SAFETY: sockaddr_storage достаточно велик для sockaddr_in6
+                let sin6 = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = v6.port().to_be();
+                sin6.sin6_addr.s6_addr = v6.ip().octets();
+                sin6.sin6_flowinfo = v6.flowinfo();
+                sin6.sin6_scope_id = v6.scope_id();
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use tokio::net::UdpSocket;
+    use std::io;
+    use std::net::SocketAddr;
+
+    pub fn recv_batch(
+        socket: &UdpSocket,
+        buf: &mut [u8],
+        slot: usize,
+        lens: &mut [usize],
+        addrs: &mut [SocketAddr],
+        truncated: &mut [bool],
+    ) -> io::Result<usize> {
+        let mut n = 0;
+        while n < lens.len() {
+            let chunk = &mut buf[n * slot..(n + 1) * slot];
+            match socket.try_recv_from(chunk) {
+                Ok((len, addr)) => {
+                    lens[n] = len;
+                    addrs[n] = addr;
+                    truncated[n] = false;
+                    n += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && n > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(n)
+    }
+
+    pub fn send_batch(socket: &UdpSocket, dest: Option<SocketAddr>, datagrams: &[&[u8]]) -> io::Result<usize> {
+        let mut n = 0;
+        for d in datagrams {
+            let res = match dest {
+                Some(addr) => socket.try_send_to(d, addr),
+                None => socket.try_send(d),
+            };
+            match res {
+                Ok(_) => n += 1,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && n > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn loopback_batches_keep_lengths_sources_and_truncation() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let to = receiver.local_addr().unwrap();
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        b.connect(to).await.unwrap();
+
+        // Датаграмм больше, чем помещается в один пакет приёма; последняя больше слота
+        let payloads: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; 1 + i as usize * 5]).collect();
+        let datagrams: Vec<&[u8]> = payloads.iter().map(Vec::as_slice).collect();
+        send_batch(&a, Some(to), &datagrams).await.unwrap();
+        let oversized = vec![0xaa; 100];
+        send_batch(&b, None, &[&oversized]).await.unwrap();
+
+        let mut batch = RecvBatch::new(4, 64);
+        let mut received = Vec::new();
+        while received.len() < 11 {
+            let n = batch.recv_from(&receiver).await.unwrap();
+            assert!(n <= 4);
+            assert_eq!(batch.len(), n);
+            for i in 0..n {
+                let (data, from, truncated) = batch.get(i);
+                received.push((data.to_vec(), from, truncated));
+            }
+        }
+        assert_eq!(received.len(), 11);
+
+        for (i, (data, from, truncated)) in received[..10].iter().enumerate() {
+            assert_eq!(data, &payloads[i]);
+            assert_eq!(*from, a.local_addr().unwrap());
+            assert!(!truncated);
+        }
+        let (data, from, truncated) = &received[10];
+        assert_eq!(data.len(), 64);
+        assert!(data.iter().all(|&b| b == 0xaa));
+        assert_eq!(*from, b.local_addr().unwrap());
+        // Обрезку видно только через MSG_TRUNC в recvmmsg
+        assert_eq!(*truncated, cfg!(target_os = "linux"));
+    }
+}
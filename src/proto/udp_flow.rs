@@ -0,0 +1,567 @@
+use dashmap::DashMap;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use std::hash::Hash;
+use std::net::{SocketAddr, IpAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use uuid::Uuid;
+
+use crate::proto::RateLimiter;
+use crate::consts::{
+    UDP_FLOW_IDLE_TIMEOUT,
+    UDP_MAX_FLOWS,
+    UDP_MAX_FLOWS_PER_IP,
+    UDP_MAX_FLOWS_PER_PLAYER,
+    UDP_PACKETS_PER_SEC,
+    UDP_PACKETS_BURST,
+    UDP_BYTES_PER_SEC,
+    UDP_BURST_BYTES,
+    UDP_REPLY_PACKETS_PER_SEC,
+    UDP_REPLY_PACKETS_BURST,
+    UDP_REPLY_BYTES_PER_SEC,
+    UDP_REPLY_BURST_BYTES
+};
+
+/// Лимиты UDP потоков
+#[derive(Clone, Debug)]
+pub struct UdpLimits {
+    pub flow_idle_timeout: Duration,
+    pub max_flows: usize,
+    pub max_flows_per_ip: usize,
+    pub max_flows_per_player: usize,
+    /// клиент -> upstream
+    pub packets_per_sec: usize,
+    pub packets_burst: usize,
+    pub bytes_per_sec: usize,
+    pub burst_bytes: usize,
+    /// upstream -> клиент
+    pub reply_packets_per_sec: usize,
+    pub reply_packets_burst: usize,
+    pub reply_bytes_per_sec: usize,
+    pub reply_burst_bytes: usize,
+}
+
+impl Default for UdpLimits {
+    fn default() -> Self {
+        Self {
+            flow_idle_timeout: UDP_FLOW_IDLE_TIMEOUT,
+            max_flows: UDP_MAX_FLOWS,
+            max_flows_per_ip: UDP_MAX_FLOWS_PER_IP,
+            max_flows_per_player: UDP_MAX_FLOWS_PER_PLAYER,
+            packets_per_sec: UDP_PACKETS_PER_SEC,
+            packets_burst: UDP_PACKETS_BURST,
+            bytes_per_sec: UDP_BYTES_PER_SEC,
+            burst_bytes: UDP_BURST_BYTES,
+            reply_packets_per_sec: UDP_REPLY_PACKETS_PER_SEC,
+            reply_packets_burst: UDP_REPLY_PACKETS_BURST,
+            reply_bytes_per_sec: UDP_REPLY_BYTES_PER_SEC,
+            reply_burst_bytes: UDP_REPLY_BURST_BYTES,
+        }
+    }
+}
+
+impl UdpLimits {
+    pub fn client_limiter(&self) -> FlowLimiter {
+        FlowLimiter::new(self.packets_per_sec, self.packets_burst, self.bytes_per_sec, self.burst_bytes)
+    }
+
+    pub fn reply_limiter(&self) -> FlowLimiter {
+        FlowLimiter::new(self.reply_packets_per_sec, self.reply_packets_burst, self.reply_bytes_per_sec, self.reply_burst_bytes)
+    }
+}
+
+/// Метка последней активности потока (мс от создания таблицы), общая для обоих направлений
+#[derive(Clone)]
+pub struct LastSeen {
+    epoch: Instant,
+    ms: Arc<AtomicU64>,
+}
+
+impl LastSeen {
+    fn new(epoch: Instant) -> Self {
+        let last = Self { epoch, ms: Arc::new(AtomicU64::new(0)) };
+        last.touch();
+        last
+    }
+
+    pub fn touch(&self) {
+        self.ms.store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle(&self, now: Instant) -> Duration {
+        let now_ms = now.duration_since(self.epoch).as_millis() as u64;
+        Duration::from_millis(now_ms.saturating_sub(self.ms.load(Ordering::Relaxed)))
+    }
+}
+
+/// Ограничение потока одновременно по пакетам и по байтам в секунду
+pub struct FlowLimiter {
+    packets: RateLimiter,
+    bytes: RateLimiter,
+}
+
+impl FlowLimiter {
+    pub fn new(packets_per_sec: usize, packets_burst: usize, bytes_per_sec: usize, bytes_burst: usize) -> Self {
+        Self {
+            packets: RateLimiter::new(packets_per_sec, packets_burst),
+            bytes: RateLimiter::new(bytes_per_sec, bytes_burst),
+        }
+    }
+
+    pub fn allow(&mut self, len: usize) -> bool {
+        self.packets.allow(1) && self.bytes.allow(len)
+    }
+}
+
+/// Один UDP поток клиента: исходящий сокет к upstream и задача, пересылающая ответы клиенту
+pub struct UdpFlow {
+    pub upstream: SocketAddr,
+    pub outbound: Arc<UdpSocket>,
+    pub last_seen: LastSeen,
+    /// Лимит пакетов клиент -> upstream. Пакеты одного клиента обрабатывает один воркер,
+    /// так что блокировка практически не конкурентна.
+    limiter: Mutex<FlowLimiter>,
+    /// Игрок Simple Voice Chat, по UUID которого создан поток
+    player: Option<Uuid>,
+    /// Поток занимает место в лимите игрока (UUID подтверждён сессией с IP клиента)
+    player_slot: bool,
+    /// Эпоха UDP сессий Router, при которой поток последний раз сверялся со своей сессией
+    session_epoch: AtomicU64,
+    reply_task: JoinHandle<()>,
+}
+
+impl UdpFlow {
+    pub fn new(
+        upstream: SocketAddr,
+        outbound: Arc<UdpSocket>,
+        last_seen: LastSeen,
+        limiter: FlowLimiter,
+        player: Option<Uuid>,
+        session_epoch: u64,
+        reply_task: JoinHandle<()>,
+    ) -> Self {
+        Self {
+            upstream,
+            outbound,
+            last_seen,
+            limiter: Mutex::new(limiter),
+            player,
+            player_slot: false,
+            session_epoch: AtomicU64::new(session_epoch),
+            reply_task,
+        }
+    }
+
+    /// Учитывать поток в лимите потоков игрока (место зарезервировано в `reserve`)
+    pub fn with_player_slot(mut self) -> Self {
+        self.player_slot = self.player.is_some();
+        self
+    }
+
+    pub fn player(&self) -> Option<&Uuid> {
+        self.player.as_ref()
+    }
+
+    /// Игрок, в лимите которого учтён поток
+    fn slot_player(&self) -> Option<Uuid> {
+        self.player.filter(|_| self.player_slot)
+    }
+
+    pub fn session_epoch(&self) -> u64 {
+        self.session_epoch.load(Ordering::Relaxed)
+    }
+
+    pub fn set_session_epoch(&self, epoch: u64) {
+        self.session_epoch.store(epoch, Ordering::Relaxed);
+    }
+
+    pub fn allow(&self, len: usize) -> bool {
+        self.limiter.lock().unwrap().allow(len)
+    }
+
+    /// Задача ответов завершилась (ошибка сокета) — поток больше не работает
+    pub fn is_closed(&self) -> bool {
+        self.reply_task.is_finished()
+    }
+}
+
+impl Drop for UdpFlow {
+    fn drop(&mut self) {
+        self.reply_task.abort();
+    }
+}
+
+/// Счётчики таблицы потоков
+#[derive(Default)]
+pub struct UdpFlowStats {
+    pub created: AtomicU64,
+    pub closed: AtomicU64,
+    pub evicted_idle: AtomicU64,
+    pub rejected_global_limit: AtomicU64,
+    pub rejected_ip_limit: AtomicU64,
+    pub rejected_player_limit: AtomicU64,
+    /// Пакеты от источников без UDP сессии
+    pub dropped_no_session: AtomicU64,
+    /// Пакеты клиент -> upstream сверх лимита потока
+    pub dropped_rate_limited: AtomicU64,
+    /// Пакеты upstream -> клиент сверх лимита потока
+    pub dropped_reply_rate_limited: AtomicU64,
+    /// Датаграммы больше буфера приёма
+    pub dropped_truncated: AtomicU64,
+}
+
+impl UdpFlowStats {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Сумма всех счётчиков отброшенных пакетов
+    pub fn drops(&self) -> u64 {
+        self.dropped_no_session.load(Ordering::Relaxed)
+            + self.dropped_rate_limited.load(Ordering::Relaxed)
+            + self.dropped_reply_rate_limited.load(Ordering::Relaxed)
+            + self.dropped_truncated.load(Ordering::Relaxed)
+    }
+}
+
+/// Почему новый поток не может быть создан
+#[derive(Debug, Clone, Copy)]
+pub enum FlowLimit {
+    Global,
+    PerIp,
+    PerPlayer,
+}
+
+/// Таблица UDP потоков client SocketAddr -> поток с ограничениями на общее число потоков,
+/// число потоков с одного IP и число адресов, на которые разветвляется сессия одного игрока.
+/// Шардированная (DashMap): воркеры читают и создают потоки без общей блокировки.
+/// Неактивные потоки удаляются в `sweep`.
+pub struct UdpFlowTable {
+    flows: DashMap<SocketAddr, Arc<UdpFlow>>,
+    count: AtomicUsize,
+    per_ip: DashMap<IpAddr, usize>,
+    per_player: DashMap<Uuid, usize>,
+    epoch: Instant,
+    limits: UdpLimits,
+    stats: Arc<UdpFlowStats>,
+}
+
+impl UdpFlowTable {
+    pub fn new(limits: UdpLimits) -> Self {
+        Self {
+            flows: DashMap::new(),
+            count: AtomicUsize::new(0),
+            per_ip: DashMap::new(),
+            per_player: DashMap::new(),
+            epoch: Instant::now(),
+            limits,
+            stats: Arc::new(UdpFlowStats::default()),
+        }
+    }
+
+    pub fn limits(&self) -> &UdpLimits {
+        &self.limits
+    }
+
+    pub fn stats(&self) -> Arc<UdpFlowStats> {
+        self.stats.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    pub fn new_last_seen(&self) -> LastSeen {
+        LastSeen::new(self.epoch)
+    }
+
+    pub fn get(&self, client: &SocketAddr) -> Option<Arc<UdpFlow>> {
+        self.flows.get(client).map(|f| f.clone())
+    }
+
+    /// Зарезервировать место под новый поток для client (отказ учитывается в счётчиках).
+    /// player — только подтверждённый игрок: поток с его UUID затем создаётся `with_player_slot`.
+    /// После успеха нужно вызвать `insert` или `unreserve`.
+    pub fn reserve(&self, client: &SocketAddr, player: Option<&Uuid>) -> Result<(), FlowLimit> {
+        if self.count.fetch_add(1, Ordering::AcqRel) >= self.limits.max_flows {
+            self.count.fetch_sub(1, Ordering::AcqRel);
+            UdpFlowStats::inc(&self.stats.rejected_global_limit);
+            return Err(FlowLimit::Global);
+        }
+        if !try_increment(&self.per_ip, client.ip(), self.limits.max_flows_per_ip) {
+            self.count.fetch_sub(1, Ordering::AcqRel);
+            UdpFlowStats::inc(&self.stats.rejected_ip_limit);
+            return Err(FlowLimit::PerIp);
+        }
+        if let Some(player) = player
+            && !try_increment(&self.per_player, *player, self.limits.max_flows_per_player)
+        {
+            self.count.fetch_sub(1, Ordering::AcqRel);
+            decrement(&self.per_ip, client.ip());
+            UdpFlowStats::inc(&self.stats.rejected_player_limit);
+            return Err(FlowLimit::PerPlayer);
+        }
+        Ok(())
+    }
+
+    /// Освободить резерв, если поток так и не был создан
+    pub fn unreserve(&self, client: &SocketAddr, player: Option<&Uuid>) {
+        self.release(client.ip(), player.copied());
+    }
+
+    /// Добавить поток под ранее полученный резерв. Если другой воркер успел создать поток
+    /// для этого клиента, резерв освобождается и возвращается существующий поток.
+    pub fn insert(&self, client: SocketAddr, flow: UdpFlow) -> Arc<UdpFlow> {
+        let player = flow.slot_player();
+        let mut created = false;
+        let flow = self.flows.entry(client)
+            .or_insert_with(|| {
+                created = true;
+                Arc::new(flow)
+            })
+            .clone();
+        if created {
+            UdpFlowStats::inc(&self.stats.created);
+        } else {
+            self.release(client.ip(), player);
+        }
+        flow
+    }
+
+    /// Удалить поток (закрыт из-за ошибки сокета), если он всё ещё актуален для client
+    pub fn remove(&self, client: &SocketAddr, flow: &Arc<UdpFlow>) -> bool {
+        match self.flows.remove_if(client, |_, f| Arc::ptr_eq(f, flow)) {
+            Some((_, removed)) => {
+                self.release(client.ip(), removed.slot_player());
+                UdpFlowStats::inc(&self.stats.closed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Удалить неактивные дольше flow_idle_timeout и закрытые потоки; возвращает число удалённых
+    pub fn sweep(&self) -> usize {
+        let now = Instant::now();
+        let idle_timeout = self.limits.flow_idle_timeout;
+        let is_expired = |f: &UdpFlow| f.is_closed() || f.last_seen.idle(now) >= idle_timeout;
+
+        let expired: Vec<SocketAddr> = self.flows.iter()
+            .filter(|e| is_expired(e.value()))
+            .map(|e| *e.key())
+            .collect();
+
+        let mut removed = 0;
+        for client in &expired {
+            if let Some((_, flow)) = self.flows.remove_if(client, |_, f| is_expired(f)) {
+                let counter = if flow.is_closed() { &self.stats.closed } else { &self.stats.evicted_idle };
+                UdpFlowStats::inc(counter);
+                self.release(client.ip(), flow.slot_player());
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Закрыть потоки, для которых выполняется условие (их UDP сессия снята или ведёт
+    /// на другой upstream); возвращает число закрытых
+    pub fn close_where(&self, pred: impl Fn(&SocketAddr, &UdpFlow) -> bool) -> usize {
+        let matching: Vec<SocketAddr> = self.flows.iter()
+            .filter(|e| pred(e.key(), e.value()))
+            .map(|e| *e.key())
+            .collect();
+
+        let mut removed = 0;
+        for client in &matching {
+            if let Some((_, flow)) = self.flows.remove_if(client, |client, f| pred(client, f)) {
+                UdpFlowStats::inc(&self.stats.closed);
+                self.release(client.ip(), flow.slot_player());
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Закрыть все потоки (остановка прокси); возвращает число закрытых
+    pub fn close_all(&self) -> usize {
+        let clients: Vec<SocketAddr> = self.flows.iter().map(|e| *e.key()).collect();
+        let mut removed = 0;
+        for client in &clients {
+            if let Some((_, flow)) = self.flows.remove(client) {
+                UdpFlowStats::inc(&self.stats.closed);
+                self.release(client.ip(), flow.slot_player());
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    fn release(&self, ip: IpAddr, player: Option<Uuid>) {
+        self.count.fetch_sub(1, Ordering::AcqRel);
+        decrement(&self.per_ip, ip);
+        if let Some(player) = player {
+            decrement(&self.per_player, player);
+        }
+    }
+}
+
+fn try_increment<K: Hash + Eq>(counts: &DashMap<K, usize>, key: K, max: usize) -> bool {
+    let mut n = counts.entry(key).or_insert(0);
+    if *n >= max {
+        return false;
+    }
+    *n += 1;
+    true
+}
+
+fn decrement<K: Hash + Eq>(counts: &DashMap<K, usize>, key: K) {
+    if let Some(mut n) = counts.get_mut(&key) {
+        *n = n.saturating_sub(1);
+    }
+    counts.remove_if(&key, |_, n| *n == 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> UdpLimits {
+        UdpLimits {
+            flow_idle_timeout: Duration::from_secs(60),
+            max_flows: 4,
+            max_flows_per_ip: 3,
+            max_flows_per_player: 2,
+            packets_per_sec: 10,
+            packets_burst: 2,
+            bytes_per_sec: 1000,
+            burst_bytes: 1500,
+            reply_packets_per_sec: 100,
+            reply_packets_burst: 3,
+            reply_bytes_per_sec: 500,
+            reply_burst_bytes: 800,
+        }
+    }
+
+    fn client(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    async fn flow(table: &UdpFlowTable, player: Option<Uuid>) -> UdpFlow {
+        let outbound = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let reply_task = tokio::spawn(std::future::pending());
+        UdpFlow::new(outbound.local_addr().unwrap(), outbound, table.new_last_seen(), table.limits().client_limiter(), player, 0, reply_task)
+    }
+
+    async fn open(table: &UdpFlowTable, addr: &str, player: Option<Uuid>) -> Result<Arc<UdpFlow>, FlowLimit> {
+        let addr = client(addr);
+        table.reserve(&addr, player.as_ref())?;
+        let flow = flow(table, player).await;
+        let flow = if player.is_some() { flow.with_player_slot() } else { flow };
+        Ok(table.insert(addr, flow))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reserve_rejects_beyond_caps() {
+        let table = UdpFlowTable::new(limits());
+        let player = Uuid::from_u128(1);
+
+        open(&table, "192.0.2.1:1000", Some(player)).await.unwrap();
+        open(&table, "192.0.2.1:1001", Some(player)).await.unwrap();
+        assert!(matches!(open(&table, "192.0.2.2:1000", Some(player)).await, Err(FlowLimit::PerPlayer)));
+
+        open(&table, "192.0.2.1:1002", None).await.unwrap();
+        assert!(matches!(open(&table, "192.0.2.1:1003", None).await, Err(FlowLimit::PerIp)));
+
+        open(&table, "192.0.2.3:1000", None).await.unwrap();
+        assert!(matches!(open(&table, "192.0.2.4:1000", None).await, Err(FlowLimit::Global)));
+
+        assert_eq!(table.len(), 4);
+        let stats = table.stats();
+        assert_eq!(stats.created.load(Ordering::Relaxed), 4);
+        assert_eq!(stats.rejected_player_limit.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.rejected_ip_limit.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.rejected_global_limit.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn duplicate_insert_and_unreserve_return_slots() {
+        let table = UdpFlowTable::new(limits());
+        let player = Uuid::from_u128(1);
+        let first = open(&table, "192.0.2.1:1000", Some(player)).await.unwrap();
+
+        // Другой воркер успел создать поток для того же клиента
+        table.reserve(&client("192.0.2.1:1000"), Some(&player)).unwrap();
+        let second = table.insert(client("192.0.2.1:1000"), flow(&table, Some(player)).await.with_player_slot());
+        assert!(Arc::ptr_eq(&first, &second));
+
+        table.reserve(&client("192.0.2.1:1001"), Some(&player)).unwrap();
+        table.unreserve(&client("192.0.2.1:1001"), Some(&player));
+
+        // Освобождены оба резерва: у игрока одно место из двух
+        open(&table, "192.0.2.1:1002", Some(player)).await.unwrap();
+        assert!(matches!(open(&table, "192.0.2.1:1003", Some(player)).await, Err(FlowLimit::PerPlayer)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sweep_frees_slots_of_idle_and_closed_flows() {
+        let table = UdpFlowTable::new(limits());
+        let player = Uuid::from_u128(1);
+        open(&table, "192.0.2.1:1000", Some(player)).await.unwrap();
+        let active = open(&table, "192.0.2.1:1001", Some(player)).await.unwrap();
+        assert!(open(&table, "192.0.2.1:1002", Some(player)).await.is_err());
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        active.last_seen.touch();
+        assert_eq!(table.sweep(), 0);
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(table.sweep(), 1);
+        assert!(table.get(&client("192.0.2.1:1000")).is_none());
+        assert_eq!(table.stats().evicted_idle.load(Ordering::Relaxed), 1);
+        open(&table, "192.0.2.1:1002", Some(player)).await.unwrap();
+
+        // Поток с завершившейся задачей ответов удаляется сразу
+        active.reply_task.abort();
+        tokio::task::yield_now().await;
+        assert_eq!(table.sweep(), 1);
+        assert_eq!(table.stats().closed.load(Ordering::Relaxed), 1);
+        open(&table, "192.0.2.1:1003", Some(player)).await.unwrap();
+        assert_eq!(table.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_packets_are_throttled_by_count_and_bytes() {
+        let table = UdpFlowTable::new(limits());
+        let flow = open(&table, "192.0.2.1:1000", None).await.unwrap();
+
+        // Пакетов в запасе два
+        assert!(flow.allow(100));
+        assert!(flow.allow(100));
+        assert!(!flow.allow(100));
+
+        // Через 200 мс добавляются два пакета, но байтов хватает только на один большой
+        tokio::time::advance(Duration::from_millis(200)).await;
+        assert!(flow.allow(1400));
+        assert!(!flow.allow(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replies_are_throttled_by_count_and_bytes() {
+        let mut limiter = limits().reply_limiter();
+        assert!(limiter.allow(100));
+        assert!(limiter.allow(100));
+        assert!(limiter.allow(100));
+        assert!(!limiter.allow(100));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(limiter.allow(700));
+        assert!(!limiter.allow(200));
+        assert!(limiter.allow(100));
+    }
+}
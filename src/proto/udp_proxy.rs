@@ -1,191 +1,406 @@
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::net::UdpSocket;
-use tokio::time::{sleep, Duration};
-use std::{io, sync::Arc, collections::HashMap};
-use std::net::{SocketAddr, IpAddr};
-use tokio::sync::Mutex as AsyncMutex;
+use tokio::task::JoinSet;
+use tokio::time::interval;
+use std::{io, sync::Arc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+use uuid::Uuid;
 
-use crate::proto::Router;
+use crate::proto::{Router, VoicePacketHeader};
+#[cfg(target_os = "linux")]
+use crate::proto::transparent;
+use crate::proto::udp_batch::{RecvBatch, send_batch};
+use crate::proto::udp_flow::{UdpFlow, UdpFlowStats, UdpFlowTable, UdpLimits};
+use crate::consts::{
+    UDP_BATCH_SIZE,
+    UDP_REPLY_BATCH_SIZE,
+    UDP_MAX_DATAGRAM,
+    UDP_SESSION_GRACE,
+    UDP_SESSION_TTL,
+    UDP_SWEEP_INTERVAL
+};
 
-/// UDP proxy: broadcast по портам upstream IP, попытка отправить с исходного client_port,
-/// создание точных mapping'ов при ответе сервера, аккуратная работа с pending.
+/// UDP proxy в стиле NAT: на каждую клиентскую сессию (IP+порт) создаётся отдельный
+/// исходящий сокет, подключённый к upstream маршрута из TCP рукопожатия.
+/// Ответы сервера приходят на этот сокет и однозначно относятся к своему клиенту.
+///
+/// Входящий порт обслуживают несколько воркеров, каждый со своим сокетом (SO_REUSEPORT):
+/// ядро распределяет клиентов между ними, датаграммы принимаются и отправляются пачками.
 pub struct UdpProxy {
-    socket: UdpSocket,
+    sockets: Vec<Arc<UdpSocket>>,
     router: Arc<Router>,
-    pending_clients: Arc<AsyncMutex<HashMap<IpAddr, Vec<SocketAddr>>>>,
-    pending_ttl_secs: u64,
+    flows: Arc<UdpFlowTable>,
+    /// Сумма счётчиков отброшенных пакетов на момент последнего отчёта
+    reported_drops: AtomicU64,
+}
+
+/// Состояние, общее для воркера и задач ответов его потоков
+struct Worker {
+    socket: Arc<UdpSocket>,
+    router: Arc<Router>,
+    flows: Arc<UdpFlowTable>,
 }
 
 impl UdpProxy {
-    pub fn new(socket: UdpSocket, router: Arc<Router>) -> Self {
+    pub fn new(sockets: Vec<UdpSocket>, router: Arc<Router>, limits: UdpLimits) -> Self {
         Self {
-            socket,
+            sockets: sockets.into_iter().map(Arc::new).collect(),
             router,
-            pending_clients: Arc::new(AsyncMutex::new(HashMap::new())),
-            pending_ttl_secs: 10,
+            flows: Arc::new(UdpFlowTable::new(limits)),
+            reported_drops: AtomicU64::new(0),
+        }
+    }
+
+    /// Привязать `workers` сокетов к addr с SO_REUSEPORT (порт 0 — один общий случайный порт)
+    pub fn bind(addr: SocketAddr, workers: usize, router: Arc<Router>, limits: UdpLimits) -> io::Result<Self> {
+        let first = bind_reuseport(addr)?;
+        let addr = first.local_addr()?;
+        let mut sockets = vec![first];
+        for _ in 1..workers.max(1) {
+            sockets.push(bind_reuseport(addr)?);
         }
+        Ok(Self::new(sockets, router, limits))
     }
 
-    pub async fn run(&mut self) -> io::Result<()> {
-        let mut buf = vec![0u8; 65535];
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sockets[0].local_addr()
+    }
 
+    /// Сокеты воркеров (все на одном порту)
+    pub fn sockets(&self) -> &[Arc<UdpSocket>] {
+        &self.sockets
+    }
+
+    pub fn flows(&self) -> Arc<UdpFlowTable> {
+        self.flows.clone()
+    }
+
+    pub async fn run(&self) -> io::Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Работать до завершения shutdown: затем воркеры останавливаются, а все потоки
+    /// закрываются (исходящие сокеты и задачи ответов освобождаются).
+    /// После ошибки можно запустить снова: сокеты и потоки сохраняются
+    pub async fn run_until(&self, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+        tokio::pin!(shutdown);
+        let mut workers = JoinSet::new();
+        for socket in &self.sockets {
+            let worker = Arc::new(Worker {
+                socket: socket.clone(),
+                router: self.router.clone(),
+                flows: self.flows.clone(),
+            });
+            workers.spawn(worker.run());
+        }
+
+        let mut sweep = interval(UDP_SWEEP_INTERVAL);
         loop {
-            let recv = self.socket.recv_from(&mut buf).await;
-            let (len, src) = match recv {
-                Ok(v) => v,
-                Err(e) => {
-                    eprintln!("UDP recv_from error: {}", e);
-                    continue;
+            tokio::select! {
+                _ = sweep.tick() => self.sweep(),
+                _ = &mut shutdown => {
+                    workers.shutdown().await;
+                    let closed = self.flows.close_all();
+                    println!("UDP proxy остановлен, закрыто потоков: {}", closed);
+                    return Ok(());
                 }
-            };
-            let data = &buf[..len];
-
-            println!("Получен udp пакет от {} ({} байт)", src, len);
-
-            // 1) Точный lookup по SocketAddr (IP+порт)
-            if let Some(upstream) = self.router.lookup_udp_for_client(&src) {
-                println!("  точное сопоставление: {} -> upstream {}", src, upstream);
-                match self.socket.send_to(data, upstream).await {
-                    Ok(sent) => println!("    отправлено {} байт на {}", sent, upstream),
-                    Err(e) => {
-                        eprintln!("    ошибка при отправке на {}: {}", upstream, e);
-                        if e.kind() == std::io::ErrorKind::ConnectionReset {
-                            self.router.unregister_udp_mapping(&src);
-                            println!("    удалён mapping {} из-за ConnectionReset", src);
-                        }
-                    }
+                Some(res) = workers.join_next() => {
+                    return match res {
+                        Ok(r) => r,
+                        Err(e) => Err(io::Error::other(e)),
+                    };
                 }
-                continue;
             }
+        }
+    }
 
-            // 2) Возможно это ответ от upstream сервера — сначала проверяем clients_for_upstream
-            let clients_for_up = self.router.clients_for_upstream(&src);
-            if !clients_for_up.is_empty() {
-                println!("  пакет от сервера {} пересылаю клиентам: {:?}", src, clients_for_up);
-                // дедупликация
-                let mut clients = clients_for_up;
-                clients.sort_unstable();
-                clients.dedup();
-                for client in clients.iter() {
-                    match self.socket.send_to(data, *client).await {
-                        Ok(sent) => println!("    отправлено {} байт клиенту {}", sent, client),
-                        Err(e) => {
-                            eprintln!("    ошибка при отправке клиенту {}: {}", client, e);
-                            if e.kind() == std::io::ErrorKind::ConnectionReset {
-                                self.router.unregister_udp_mapping(client);
-                                println!("    удалён mapping {} из-за ConnectionReset", client);
-                            }
-                        }
-                    }
-                }
+    /// Периодическая очистка неактивных потоков и устаревших UDP сессий в Router.
+    /// Потоки, чьи сессии удалены вместе с завершившейся TCP сессией, закрываются сразу
+    fn sweep(&self) {
+        let mut evicted = self.flows.sweep();
+        let expired = self.router.expire_udp_sessions(UDP_SESSION_TTL, UDP_SESSION_GRACE);
+        let epoch = self.router.udp_sessions_epoch();
+        evicted += self.flows.close_where(|client, flow| {
+            flow.session_epoch() != epoch && !self.router.udp_session_is(&client.ip(), flow.player(), flow.upstream)
+        });
+        let stats = self.flows.stats();
+        let drops = stats.drops();
+        if evicted > 0 || expired > 0 || drops != self.reported_drops.swap(drops, Ordering::Relaxed) {
+            println!(
+                "UDP очистка: удалено потоков {}, сессий {}; активно {} (создано {}, закрыто {}, по таймауту {}, отказов по лимиту {}/{}/{}; отброшено без сессии {}, по rate limit {}/{}, обрезанных {})",
+                evicted,
+                expired,
+                self.flows.len(),
+                stats.created.load(Ordering::Relaxed),
+                stats.closed.load(Ordering::Relaxed),
+                stats.evicted_idle.load(Ordering::Relaxed),
+                stats.rejected_global_limit.load(Ordering::Relaxed),
+                stats.rejected_ip_limit.load(Ordering::Relaxed),
+                stats.rejected_player_limit.load(Ordering::Relaxed),
+                stats.dropped_no_session.load(Ordering::Relaxed),
+                stats.dropped_rate_limited.load(Ordering::Relaxed),
+                stats.dropped_reply_rate_limited.load(Ordering::Relaxed),
+                stats.dropped_truncated.load(Ordering::Relaxed),
+            );
+        }
+    }
+}
 
-                // Если были pending клиенты для этого upstream IP — создаём точные mapping'и
-                let up_ip = src.ip();
-                let mut pending = self.pending_clients.lock().await;
-                if let Some(pending_list) = pending.remove(&up_ip) {
-                    for client in pending_list {
-                        self.router.register_udp_mapping(client, src);
-                        println!("  CREATED mapping from pending: {} -> {}", client, src);
-                    }
+impl Worker {
+    async fn run(self: Arc<Self>) -> io::Result<()> {
+        let stats = self.flows.stats();
+        let mut batch = RecvBatch::new(UDP_BATCH_SIZE, UDP_MAX_DATAGRAM);
+        // Пакеты пачки, сгруппированные по клиенту (порядок внутри потока сохраняется)
+        let mut groups: Vec<(SocketAddr, Arc<UdpFlow>, Vec<usize>)> = Vec::with_capacity(UDP_BATCH_SIZE);
+
+        loop {
+            // Временные ошибки пропускаем; остальные завершают воркер, чтобы supervisor
+            // перезапустил UDP прокси, а не крутил цикл на неисправном сокете
+            if let Err(e) = batch.recv_from(&self.socket).await {
+                if is_transient(&e) {
+                    eprintln!("UDP recv_from error: {}", e);
+                    continue;
                 }
-                continue;
+                return Err(e);
             }
 
-            // 2b) Если clients_for_upstream пуст, но есть pending для этого upstream IP — это тоже ответ сервера
-            {
-                let up_ip = src.ip();
-                let mut pending = self.pending_clients.lock().await;
-                if let Some(pending_list) = pending.remove(&up_ip) {
-                    println!("  ответ от upstream {} для pending клиентов: {:?}", src, pending_list);
-                    // пересылаем ответ каждому pending клиенту и создаём точный mapping
-                    for client in pending_list.iter() {
-                        // регистрируем mapping client -> src
-                        self.router.register_udp_mapping(*client, src);
-                        println!("  CREATED mapping from pending: {} -> {}", client, src);
-
-                        match self.socket.send_to(data, *client).await {
-                            Ok(sent) => println!("    отправлено {} байт клиенту {}", sent, client),
-                            Err(e) => {
-                                eprintln!("    ошибка при отправке клиенту {}: {}", client, e);
-                                if e.kind() == std::io::ErrorKind::ConnectionReset {
-                                    self.router.unregister_udp_mapping(client);
-                                    println!("    удалён mapping {} из-за ConnectionReset", client);
-                                }
-                            }
-                        }
-                    }
-                    // уже обработали как ответ сервера
+            for i in 0..batch.len() {
+                let (data, src, truncated) = batch.get(i);
+                if truncated {
+                    UdpFlowStats::inc(&stats.dropped_truncated);
                     continue;
                 }
-            }
 
-            // 3) Нет точного mapping и это не ответ от upstream — пробуем ip->ip mapping
-            let client_ip = src.ip();
-            if let Some(up_ip) = self.router.lookup_udp_ip_for_client(&client_ip) {
-                println!("  найден IP->IP mapping: {} -> {}", client_ip, up_ip);
+                let flow = match groups.iter().find(|(client, _, _)| *client == src) {
+                    Some((_, flow, _)) => Some(flow.clone()),
+                    None => self.flow_for(src, data).await,
+                };
+                let Some(flow) = flow else { continue };
 
-                // Получаем все upstream ip:port для up_ip (в конфиге может быть несколько серверов на одном IP)
-                let upstream_addrs = self.router.upstream_addrs_for_ip(&up_ip);
-                if upstream_addrs.is_empty() {
-                    println!("  upstream_addrs_for_ip({}) пустой — нет портов для рассылки", up_ip);
+                if !flow.allow(data.len()) {
+                    UdpFlowStats::inc(&stats.dropped_rate_limited);
                     continue;
                 }
+                flow.last_seen.touch();
 
-                // Broadcast: отправляем пакет на каждый порт upstream_addrs.
-                // Попытаемся отправить с исходного client.port() — создаём временный сокет, привязанный к этому порту.
-                let client_port = src.port();
-                for up_addr in upstream_addrs.iter() {
-                    let bind_addr = SocketAddr::new(std::net::IpAddr::from([0,0,0,0]), client_port);
-                    match UdpSocket::bind(bind_addr).await {
-                        Ok(temp_sock) => {
-                            match temp_sock.send_to(data, *up_addr).await {
-                                Ok(sent) => println!("    отправлено {} байт на {} (source port {})", sent, up_addr, client_port),
-                                Err(e) => eprintln!("    ошибка при отправке через temp socket на {}: {}", up_addr, e),
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("    не удалось bind 0.0.0.0:{} ({}) — fallback на главный сокет", client_port, e);
-                            match self.socket.send_to(data, *up_addr).await {
-                                Ok(sent) => println!("    отправлено {} байт на {} (fallback)", sent, up_addr),
-                                Err(e) => eprintln!("    ошибка при отправке на {}: {}", up_addr, e),
-                            }
-                        }
+                match groups.iter_mut().find(|(client, _, _)| *client == src) {
+                    Some((_, _, idx)) => idx.push(i),
+                    None => groups.push((src, flow, vec![i])),
+                }
+            }
+
+            for (src, flow, idx) in groups.drain(..) {
+                let datagrams: Vec<&[u8]> = idx.iter().map(|&i| batch.get(i).0).collect();
+                if let Err(e) = send_batch(&flow.outbound, None, &datagrams).await {
+                    eprintln!("Ошибка отправки {} -> {}: {}", src, flow.upstream, e);
+                    if matches!(e.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused)
+                        && self.flows.remove(&src, &flow)
+                    {
+                        println!("UDP сессия {} закрыта", src);
                     }
                 }
+            }
+        }
+    }
+
+    /// Поток для src: существующий или новый (если есть сессия из TCP рукопожатия и позволяют лимиты)
+    async fn flow_for(&self, src: SocketAddr, data: &[u8]) -> Option<Arc<UdpFlow>> {
+        if let Some(flow) = self.flows.get(&src) {
+            // Поток, чья задача ответов завершилась (сокет закрыт/ошибка), пересоздаётся.
+            // Так же и поток, чья сессия снята или теперь ведёт на другой upstream
+            // (игрок перешёл на другой сервер): сверка нужна, только если сессии менялись
+            let epoch = self.router.udp_sessions_epoch();
+            let current = flow.session_epoch() == epoch
+                || self.router.udp_session_is(&src.ip(), flow.player(), flow.upstream);
+            if !flow.is_closed() && current {
+                flow.set_session_epoch(epoch);
+                return Some(flow);
+            }
+            if self.flows.remove(&src, &flow) && !current {
+                println!("UDP сессия {} -> {} закрыта: сессия игрока снята или перенаправлена", src, flow.upstream);
+            }
+        }
+
+        // Без сессии из TCP рукопожатия пакет не пересылается никуда
+        let Some((upstream, player)) = self.resolve_upstream(src, data) else {
+            UdpFlowStats::inc(&self.flows.stats().dropped_no_session);
+            return None;
+        };
+        // Лимит игрока действует, только если UUID зарегистрирован TCP сессией с этого же IP:
+        // иначе чужой клиент с UUID игрока занял бы все его места. Остальных ограничивает лимит IP
+        let slot_player = player.filter(|p| self.router.player_udp_session_from(p, &src.ip()));
+        if let Err(limit) = self.flows.reserve(&src, slot_player.as_ref()) {
+            eprintln!("UDP поток {} -> {} отклонён: лимит {:?}", src, upstream, limit);
+            return None;
+        }
+        match self.open_flow(src, upstream, player).await {
+            Ok(flow) => {
+                println!("UDP сессия {} -> {} создана", src, upstream);
+                let flow = if slot_player.is_some() { flow.with_player_slot() } else { flow };
+                Some(self.flows.insert(src, flow))
+            }
+            Err(e) => {
+                self.flows.unreserve(&src, slot_player.as_ref());
+                eprintln!("Не удалось создать UDP сессию {} -> {}: {}", src, upstream, e);
+                None
+            }
+        }
+    }
+
+    /// Найти upstream для новой сессии: сначала по UUID игрока из пакета Simple Voice Chat
+    /// (различает игроков за одним NAT), затем по IP клиента из TCP рукопожатия.
+    /// Возвращает upstream и UUID игрока, если поток привязан к игроку.
+    /// Сессии на upstream, которого уже нет среди маршрутов, игнорируются.
+    fn resolve_upstream(&self, src: SocketAddr, data: &[u8]) -> Option<(SocketAddr, Option<Uuid>)> {
+        let resolved = match VoicePacketHeader::parse(data)
+            .and_then(|h| self.router.lookup_player_udp_session(&h.player).map(|up| (up, Some(h.player))))
+        {
+            Some(v) => Some(v),
+            None => self.router.lookup_udp_session(&src.ip()).map(|up| (up, None)),
+        };
+        let (upstream, player) = resolved?;
+        self.router.route_for_upstream(&upstream)?;
+        if let Some(player) = player {
+            println!("UDP {}: игрок {} -> {}", src, player, upstream);
+        }
+        Some((upstream, player))
+    }
 
-                // Добавляем клиента в pending для этого upstream IP (чтобы при ответе сервера создать mapping)
-                {
-                    let mut pending = self.pending_clients.lock().await;
-                    let entry = pending.entry(up_ip).or_insert_with(Vec::new);
-                    if !entry.contains(&src) {
-                        entry.push(src);
-                        println!("  добавлен в pending_clients[{}]: {}", up_ip, src);
-
-                        // spawn TTL task только если мы действительно добавили клиента
-                        let pending_clone = self.pending_clients.clone();
-                        let up_ip_clone = up_ip;
-                        let client_clone = src;
-                        let ttl = self.pending_ttl_secs;
-                        tokio::spawn(async move {
-                            sleep(Duration::from_secs(ttl)).await;
-                            let mut pending = pending_clone.lock().await;
-                            if let Some(vec) = pending.get_mut(&up_ip_clone) {
-                                vec.retain(|&c| c != client_clone);
-                                if vec.is_empty() {
-                                    pending.remove(&up_ip_clone);
-                                }
-                                println!("  pending TTL expired: removed {} from pending[{}]", client_clone, up_ip_clone);
-                            }
-                        });
+    /// Создать исходящий сокет, подключённый к upstream, и задачу пересылки ответов клиенту
+    async fn open_flow(&self, client: SocketAddr, upstream: SocketAddr, player: Option<Uuid>) -> io::Result<UdpFlow> {
+        let bind_ip = match upstream.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        // В прозрачном режиме голосовой сервер видит настоящий адрес клиента
+        #[cfg(target_os = "linux")]
+        let outbound = match self.router.route_for_upstream(&upstream).is_some_and(|r| r.options.transparent) {
+            true => transparent::bind_udp(transparent::source_ip(client.ip(), upstream)?)?,
+            false => UdpSocket::bind(SocketAddr::new(bind_ip, 0)).await?,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let outbound = UdpSocket::bind(SocketAddr::new(bind_ip, 0)).await?;
+        outbound.connect(upstream).await?;
+        let outbound = Arc::new(outbound);
+
+        let last_seen = self.flows.new_last_seen();
+        let reply_seen = last_seen.clone();
+        let reply_sock = outbound.clone();
+        let listener = self.socket.clone();
+        let stats = self.flows.stats();
+        // Ответы сервера тоже ограничены, чтобы поток нельзя было использовать для усиления трафика
+        let mut reply_limiter = self.flows.limits().reply_limiter();
+        let reply_task = tokio::spawn(async move {
+            let mut batch = RecvBatch::new(UDP_REPLY_BATCH_SIZE, UDP_MAX_DATAGRAM);
+            loop {
+                if let Err(e) = batch.recv_from(&reply_sock).await {
+                    eprintln!("Ошибка приёма от {} для {}: {}", upstream, client, e);
+                    return;
+                }
+                let mut datagrams: Vec<&[u8]> = Vec::with_capacity(batch.len());
+                for i in 0..batch.len() {
+                    let (data, _, truncated) = batch.get(i);
+                    if truncated {
+                        UdpFlowStats::inc(&stats.dropped_truncated);
+                    } else if !reply_limiter.allow(data.len()) {
+                        UdpFlowStats::inc(&stats.dropped_reply_rate_limited);
                     } else {
-                        println!("  клиент {} уже в pending_clients[{}], TTL не перезапускается", src, up_ip);
+                        datagrams.push(data);
                     }
                 }
-
-                continue;
+                if datagrams.is_empty() {
+                    continue;
+                }
+                reply_seen.touch();
+                if let Err(e) = send_batch(&listener, Some(client), &datagrams).await {
+                    eprintln!("Ошибка отправки клиенту {}: {}", client, e);
+                }
             }
+        });
+
+        let limiter = self.flows.limits().client_limiter();
+        let epoch = self.router.udp_sessions_epoch();
+        Ok(UdpFlow::new(upstream, outbound, last_seen, limiter, player, epoch, reply_task))
+    }
+}
+
+/// Ошибка приёма, после которой сокет остаётся рабочим: ICMP от прежних получателей
+/// (ConnectionRefused/ConnectionReset) и прерванный или несостоявшийся вызов
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// UDP сокет с SO_REUSEPORT, чтобы несколько воркеров слушали один порт
+fn bind_reuseport(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+    use crate::proto::Route;
+    use crate::proto::voicechat::MAGIC_BYTE;
+    use crate::consts::UDP_MAX_FLOWS_PER_PLAYER;
+
+    fn voice_packet(player: Uuid) -> Vec<u8> {
+        let mut data = vec![MAGIC_BYTE];
+        data.extend_from_slice(player.as_bytes());
+        data.extend_from_slice(b"payload");
+        data
+    }
+
+    /// Дошёл ли до upstream очередной пакет
+    async fn forwarded(upstream: &UdpSocket) -> bool {
+        let mut buf = [0u8; 64];
+        timeout(Duration::from_millis(300), upstream.recv_from(&mut buf)).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn spoofed_player_uuid_does_not_take_player_slots() {
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        let router = Arc::new(Router::new());
+        router.replace_routes(vec![Route::new("a".to_string(), upstream_addr, Some(upstream_addr))]);
+        let player = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        let _session = router.claim_udp_session(Some("127.0.0.1".parse().unwrap()), Some(player), upstream_addr);
+
+        let proxy = Arc::new(UdpProxy::bind("127.0.0.1:0".parse().unwrap(), 1, router, UdpLimits::default()).unwrap());
+        let proxy_addr = proxy.local_addr().unwrap();
+        tokio::spawn({
+            let proxy = proxy.clone();
+            async move { proxy.run().await }
+        });
+
+        // Клиент с другого IP шлёт пакеты с UUID игрока с разных портов: он ограничен лимитом IP,
+        // но места игрока не занимает
+        let mut clients = Vec::new();
+        for _ in 0..UDP_MAX_FLOWS_PER_PLAYER + 2 {
+            let spoofer = UdpSocket::bind("127.0.0.2:0").await.unwrap();
+            spoofer.send_to(&voice_packet(player), proxy_addr).await.unwrap();
+            assert!(forwarded(&upstream).await);
+            clients.push(spoofer);
+        }
 
-            // 4) Ничего не найдено — логируем
-            println!("  нет сопоставления для {}", src);
+        // Сам игрок получает все свои места
+        for _ in 0..UDP_MAX_FLOWS_PER_PLAYER {
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.send_to(&voice_packet(player), proxy_addr).await.unwrap();
+            assert!(forwarded(&upstream).await);
+            clients.push(client);
         }
+        let extra = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        extra.send_to(&voice_packet(player), proxy_addr).await.unwrap();
+        assert!(!forwarded(&upstream).await, "лимит игрока для подтверждённых потоков сохраняется");
     }
 }
@@ -24,6 +24,19 @@ impl VarInt {
         }
         Ok(result)
     }
+
+    pub fn write(value: i32, buf: &mut Vec<u8>) {
+        let mut v = value as u32;
+        loop {
+            let byte = (v & 0x7F) as u8;
+            v >>= 7;
+            if v == 0 {
+                buf.push(byte);
+                return;
+            }
+            buf.push(byte | 0x80);
+        }
+    }
 }
 
 pub fn read_varint_string_from_slice(buf: &mut &[u8]) -> Result<String> {
@@ -37,3 +50,8 @@ pub fn read_varint_string_from_slice(buf: &mut &[u8]) -> Result<String> {
     *buf = &buf[len..];
     Ok(res)
 }
+
+pub fn write_varint_string(s: &str, buf: &mut Vec<u8>) {
+    VarInt::write(s.len() as i32, buf);
+    buf.extend_from_slice(s.as_bytes());
+}
@@ -0,0 +1,58 @@
+use uuid::Uuid;
+
+/// Первый байт каждого UDP пакета Simple Voice Chat
+pub const MAGIC_BYTE: u8 = 0xFF;
+
+/// Заголовок UDP пакета Simple Voice Chat (клиент -> сервер):
+/// `0xFF | UUID игрока (16 байт) | зашифрованный секретом игрока payload`.
+/// Пакеты сервер -> клиент UUID не содержат.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoicePacketHeader {
+    pub player: Uuid,
+}
+
+impl VoicePacketHeader {
+    pub const LEN: usize = 1 + 16;
+
+    /// Разобрать заголовок клиентского пакета; None, если это не пакет голосового чата
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() <= Self::LEN || data[0] != MAGIC_BYTE {
+            return None;
+        }
+        let uuid: [u8; 16] = data[1..Self::LEN].try_into().ok()?;
+        Some(Self { player: Uuid::from_bytes(uuid) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYER: Uuid = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+
+    fn packet(magic: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![magic];
+        data.extend_from_slice(PLAYER.as_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn parses_player_uuid() {
+        assert_eq!(VoicePacketHeader::parse(&packet(MAGIC_BYTE, &[1, 2, 3])), Some(VoicePacketHeader { player: PLAYER }));
+    }
+
+    #[test]
+    fn rejects_packet_without_magic_byte() {
+        assert_eq!(VoicePacketHeader::parse(&packet(0x00, &[1, 2, 3])), None);
+    }
+
+    #[test]
+    fn rejects_short_packets() {
+        let data = packet(MAGIC_BYTE, &[]);
+        // Без payload и короче заголовка
+        for len in 0..=VoicePacketHeader::LEN {
+            assert_eq!(VoicePacketHeader::parse(&data[..len]), None, "len {}", len);
+        }
+    }
+}
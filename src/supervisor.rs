@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use crate::consts::{SUPERVISOR_BACKOFF_MAX, SUPERVISOR_BACKOFF_MIN, SUPERVISOR_MAX_FAILURES, SUPERVISOR_STABLE_AFTER};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubsystemState {
+    Running,
+    /// Упала и ждёт перезапуска
+    Restarting { failures: u32, error: String },
+    /// Упала SUPERVISOR_MAX_FAILURES раз подряд: прокси считается неисправным,
+    /// но перезапуски продолжаются
+    Failed { failures: u32, error: String },
+    Stopped,
+}
+
+impl fmt::Display for SubsystemState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => f.write_str("работает"),
+            Self::Restarting { failures, error } => write!(f, "перезапуск (сбоев подряд {}: {})", failures, error),
+            Self::Failed { failures, error } => write!(f, "неисправна (сбоев подряд {}: {})", failures, error),
+            Self::Stopped => f.write_str("остановлена"),
+        }
+    }
+}
+
+/// Перезапускает упавшие фоновые подсистемы (UDP прокси и т.п.) с нарастающей паузой
+/// и хранит их состояние. Подсистема, завершившаяся без ошибки, не перезапускается.
+#[derive(Clone)]
+pub struct Supervisor {
+    states: Arc<Mutex<BTreeMap<&'static str, SubsystemState>>>,
+    stop: watch::Receiver<bool>,
+}
+
+impl Supervisor {
+    /// `stop` прерывает паузу перед перезапуском
+    pub fn new(stop: watch::Receiver<bool>) -> Self {
+        Self { states: Arc::default(), stop }
+    }
+
+    /// Запустить подсистему под надзором. `start` вызывается при каждом (пере)запуске;
+    /// ошибка или паника запущенной задачи считаются сбоем.
+    pub fn spawn<F, Fut>(&self, name: &'static str, mut start: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = io::Result<()>> + Send + 'static,
+    {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut failures = 0;
+            let mut backoff = SUPERVISOR_BACKOFF_MIN;
+            loop {
+                // Неисправной подсистема остаётся, пока не проработает SUPERVISOR_STABLE_AFTER
+                if failures < SUPERVISOR_MAX_FAILURES {
+                    this.set(name, SubsystemState::Running);
+                }
+                let mut task = tokio::spawn(start());
+                let result = tokio::select! {
+                    res = &mut task => res,
+                    _ = sleep(SUPERVISOR_STABLE_AFTER) => {
+                        failures = 0;
+                        backoff = SUPERVISOR_BACKOFF_MIN;
+                        this.set(name, SubsystemState::Running);
+                        task.await
+                    }
+                };
+                let error = match result {
+                    Ok(Ok(())) => {
+                        this.set(name, SubsystemState::Stopped);
+                        return;
+                    }
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) => e.to_string(),
+                };
+
+                failures += 1;
+                eprintln!("\x1b[31m{}: сбой {} подряд: {}; перезапуск через {} с\x1b[0m", name, failures, error, backoff.as_secs());
+                this.set(name, match failures >= SUPERVISOR_MAX_FAILURES {
+                    true => SubsystemState::Failed { failures, error },
+                    false => SubsystemState::Restarting { failures, error },
+                });
+
+                let mut stop = this.stop.clone();
+                tokio::select! {
+                    _ = sleep(backoff) => {}
+                    _ = stop.wait_for(|stop| *stop) => {
+                        this.set(name, SubsystemState::Stopped);
+                        return;
+                    }
+                }
+                backoff = (backoff * 2).min(SUPERVISOR_BACKOFF_MAX);
+            }
+        })
+    }
+
+    fn set(&self, name: &'static str, state: SubsystemState) {
+        self.states.lock().unwrap().insert(name, state);
+    }
+
+    pub fn states(&self) -> Vec<(&'static str, SubsystemState)> {
+        self.states.lock().unwrap().iter().map(|(name, state)| (*name, state.clone())).collect()
+    }
+
+    /// false — какая-то подсистема неисправна
+    pub fn healthy(&self) -> bool {
+        !self.states.lock().unwrap().values().any(|s| matches!(s, SubsystemState::Failed { .. }))
+    }
+
+    /// Подсистемы не в рабочем состоянии; None — все работают
+    pub fn problems(&self) -> Option<String> {
+        let problems: Vec<String> = self.states().into_iter()
+            .filter(|(_, state)| matches!(state, SubsystemState::Restarting { .. } | SubsystemState::Failed { .. }))
+            .map(|(name, state)| format!("{}: {}", name, state))
+            .collect();
+        (!problems.is_empty()).then(|| problems.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn state(supervisor: &Supervisor, name: &str) -> SubsystemState {
+        supervisor.states().into_iter().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_with_backoff_until_failed_then_recovers() {
+        let (_stop_tx, stop) = watch::channel(false);
+        let supervisor = Supervisor::new(stop);
+        let started = Instant::now();
+        let attempts: Arc<Mutex<Vec<u64>>> = Arc::default();
+
+        let log = attempts.clone();
+        let handle = supervisor.spawn("udp", move || {
+            let mut log = log.lock().unwrap();
+            log.push(started.elapsed().as_secs());
+            let attempt = log.len();
+            async move {
+                match attempt {
+                    // Проработала дольше SUPERVISOR_STABLE_AFTER, затем упала
+                    6 => {
+                        sleep(SUPERVISOR_STABLE_AFTER + Duration::from_secs(1)).await;
+                        Err(io::Error::other("сбой после долгой работы"))
+                    }
+                    7 => Ok(()),
+                    _ => Err(io::Error::other("bind: адрес занят")),
+                }
+            }
+        });
+
+        sleep(Duration::from_secs(20)).await;
+        assert_eq!(state(&supervisor, "udp"), SubsystemState::Failed { failures: 5, error: "bind: адрес занят".to_string() });
+        assert!(!supervisor.healthy());
+        assert!(supervisor.problems().unwrap().starts_with("udp: неисправна"));
+
+        // Перезапуски продолжаются, но неисправной подсистема остаётся до стабильной работы
+        sleep(Duration::from_secs(30)).await;
+        assert!(!supervisor.healthy());
+
+        sleep(Duration::from_millis(42_500)).await;
+        assert_eq!(state(&supervisor, "udp"), SubsystemState::Restarting { failures: 1, error: "сбой после долгой работы".to_string() });
+        assert!(supervisor.healthy());
+
+        handle.await.unwrap();
+        assert_eq!(state(&supervisor, "udp"), SubsystemState::Stopped);
+        assert!(supervisor.problems().is_none());
+        // Пауза удваивается от SUPERVISOR_BACKOFF_MIN и сбрасывается после стабильной работы
+        assert_eq!(*attempts.lock().unwrap(), [0, 1, 3, 7, 15, 31, 93]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panic_counts_as_failure_and_stop_interrupts_backoff() {
+        let (stop_tx, stop) = watch::channel(false);
+        let supervisor = Supervisor::new(stop);
+        let handle = supervisor.spawn("dns", || async { panic!("сломалось") });
+
+        sleep(Duration::from_millis(500)).await;
+        assert!(matches!(state(&supervisor, "dns"), SubsystemState::Restarting { failures: 1, .. }));
+        assert!(supervisor.healthy());
+        assert!(supervisor.problems().unwrap().starts_with("dns: перезапуск"));
+
+        stop_tx.send(true).unwrap();
+        handle.await.unwrap();
+        assert_eq!(state(&supervisor, "dns"), SubsystemState::Stopped);
+    }
+}
@@ -0,0 +1,245 @@
+//! Интеграция с systemd: активация сокетами (LISTEN_FDS) и уведомления sd_notify —
+//! готовность, статус и watchdog. Без переменных окружения systemd ничего не делает.
+use socket2::{Socket, Type};
+use std::io;
+use std::net::{TcpListener, UdpSocket};
+use std::os::fd::RawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+use crate::handoff::take_fd;
+
+/// Первый дескриптор, передаваемый systemd при активации сокетами
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Переменные systemd, относящиеся к конкретному pid: при запуске нового процесса их нужно убрать
+pub const PID_BOUND_VARS: [&str; 4] = ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES", "WATCHDOG_PID"];
+
+/// Сокеты, полученные от systemd (ListenStream= и ListenDatagram= в .socket юните)
+pub struct ActivatedSockets {
+    pub tcp: Option<TcpListener>,
+    pub udp: Vec<UdpSocket>,
+}
+
+/// Забрать сокеты активации; None — процесс запущен не через .socket юнит
+pub fn activated_sockets() -> io::Result<Option<ActivatedSockets>> {
+    if std::env::var("LISTEN_PID").ok().and_then(|p| p.parse::<u32>().ok()) != Some(std::process::id()) {
+        return Ok(None);
+    }
+    let Ok(count) = std::env::var("LISTEN_FDS") else {
+        return Ok(None);
+    };
+    let count: RawFd = count.parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("LISTEN_FDS: некорректное число '{}'", count)))?;
+
+    let mut sockets = ActivatedSockets { tcp: None, udp: Vec::new() };
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        let socket = Socket::from(take_fd(fd)?);
+        match socket.r#type()? {
+            Type::STREAM if sockets.tcp.is_none() => sockets.tcp = Some(socket.into()),
+            Type::DGRAM => sockets.udp.push(socket.into()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("LISTEN_FDS: дескриптор {} не TCP и не UDP сокет (или второй TCP)", fd),
+                ));
+            }
+        }
+    }
+    Ok(Some(sockets))
+}
+
+/// Отправитель уведомлений в NOTIFY_SOCKET (Type=notify)
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// None — NOTIFY_SOCKET не задан (запуск не из systemd или Type≠notify)
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let addr = match path.as_bytes().strip_prefix(b"@") {
+            // Абстрактное пространство имён Linux
+            #[cfg(target_os = "linux")]
+            Some(name) => <SocketAddr as std::os::linux::net::SocketAddrExt>::from_abstract_name(name)?,
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "NOTIFY_SOCKET: абстрактные сокеты не поддерживаются")),
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        Ok(Some(Self { socket: UnixDatagram::unbound()?, addr, watchdog: watchdog_interval() }))
+    }
+
+    /// Период, в течение которого systemd ждёт WATCHDOG=1 (WatchdogSec=); None — watchdog выключен
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Отправить строки состояния, например "READY=1\nSTATUS=..."
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.addr).map(|_| ())
+    }
+
+    /// Прокси готов принимать соединения. MAINPID передаётся всегда: после обновления
+    /// бинаря главным процессом сервиса становится новый процесс.
+    pub fn ready(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("MAINPID={}\nREADY=1\nSTATUS={}", std::process::id(), status))
+    }
+
+    /// Периодический отчёт: статус и, если включён watchdog, подтверждение, что runtime жив.
+    /// Сбой подсистемы (например, UDP) виден в статусе: из-за него systemd не должен
+    /// перезапускать весь прокси вместе с TCP сессиями игроков.
+    pub fn heartbeat(&self, status: &str) -> io::Result<()> {
+        match self.watchdog {
+            Some(_) => self.notify(&format!("WATCHDOG=1\nSTATUS={}", status)),
+            None => self.notify(&format!("STATUS={}", status)),
+        }
+    }
+
+    /// Начата перезагрузка конфига (SIGHUP); по её завершении снова отправляется `ready`.
+    /// MONOTONIC_USEC нужен systemd, чтобы сопоставить уведомление с сигналом (Type=notify-reload)
+    pub fn reloading(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("RELOADING=1\nMONOTONIC_USEC={}\nSTATUS={}", monotonic_usec(), status))
+    }
+
+    pub fn stopping(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STOPPING=1\nSTATUS={}", status))
+    }
+}
+
+/// CLOCK_MONOTONIC в микросекундах, как его считает systemd
+fn monotonic_usec() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: clock_gettime только заполняет переданную структуру
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
+
+/// WATCHDOG_USEC, если он адресован этому процессу (WATCHDOG_PID не задан или совпадает)
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Тесты меняют переменные окружения процесса: выполняются по одному
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn set_env(vars: &[(&str, Option<&str>)]) {
+        for (name, value) in vars {
+            // SAFETY: другие тесты не читают эти переменные, пока держится ENV
+            unsafe {
+                match value {
+                    Some(v) => std::env::set_var(name, v),
+                    None => std::env::remove_var(name),
+                }
+            }
+        }
+    }
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0u8; 1024];
+        let n = socket.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn notifications_reach_notify_socket() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("mc-proxy-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let _ = std::fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        systemd.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+
+        set_env(&[
+            ("NOTIFY_SOCKET", path.to_str()),
+            ("WATCHDOG_USEC", Some("2000000")),
+            ("WATCHDOG_PID", None),
+        ]);
+        let notifier = Notifier::from_env().unwrap().expect("NOTIFY_SOCKET задан");
+        set_env(&[("NOTIFY_SOCKET", None), ("WATCHDOG_USEC", None)]);
+        assert_eq!(notifier.watchdog(), Some(Duration::from_secs(2)));
+
+        notifier.ready("готов").unwrap();
+        assert_eq!(recv(&systemd), format!("MAINPID={}\nREADY=1\nSTATUS=готов", std::process::id()));
+
+        notifier.reloading("перезагрузка").unwrap();
+        let reloading = recv(&systemd);
+        let lines: Vec<&str> = reloading.lines().collect();
+        assert_eq!(lines[0], "RELOADING=1");
+        assert!(lines[1].strip_prefix("MONOTONIC_USEC=").unwrap().parse::<u64>().unwrap() > 0);
+        assert_eq!(lines[2], "STATUS=перезагрузка");
+
+        notifier.heartbeat("работает").unwrap();
+        assert_eq!(recv(&systemd), "WATCHDOG=1\nSTATUS=работает");
+
+        notifier.stopping("остановка").unwrap();
+        assert_eq!(recv(&systemd), "STOPPING=1\nSTATUS=остановка");
+
+        // Без WatchdogSec= отправляется только статус
+        set_env(&[("NOTIFY_SOCKET", path.to_str())]);
+        let notifier = Notifier::from_env().unwrap().expect("NOTIFY_SOCKET задан");
+        set_env(&[("NOTIFY_SOCKET", None)]);
+        assert_eq!(notifier.watchdog(), None);
+        notifier.heartbeat("UDP прокси: неисправна").unwrap();
+        assert_eq!(recv(&systemd), "STATUS=UDP прокси: неисправна");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn watchdog_for_other_pid_is_ignored() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        set_env(&[("WATCHDOG_USEC", Some("2000000")), ("WATCHDOG_PID", Some("1"))]);
+        let interval = watchdog_interval();
+        set_env(&[("WATCHDOG_USEC", None), ("WATCHDOG_PID", None)]);
+        assert_eq!(interval, None);
+    }
+
+    #[test]
+    fn listen_fds_for_other_pid_are_ignored() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let other = (std::process::id() + 1).to_string();
+        set_env(&[("LISTEN_PID", Some(&other)), ("LISTEN_FDS", Some("2"))]);
+        let sockets = activated_sockets();
+        set_env(&[("LISTEN_PID", None), ("LISTEN_FDS", None)]);
+        assert!(sockets.unwrap().is_none());
+    }
+
+    #[test]
+    fn zero_listen_fds_activate_nothing() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let pid = std::process::id().to_string();
+        set_env(&[("LISTEN_PID", Some(&pid)), ("LISTEN_FDS", Some("0"))]);
+        let sockets = activated_sockets();
+        set_env(&[("LISTEN_PID", None), ("LISTEN_FDS", None)]);
+        let sockets = sockets.unwrap().expect("LISTEN_PID совпадает");
+        assert!(sockets.tcp.is_none());
+        assert!(sockets.udp.is_empty());
+    }
+
+    #[test]
+    fn invalid_listen_fds_is_an_error() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let pid = std::process::id().to_string();
+        set_env(&[("LISTEN_PID", Some(&pid)), ("LISTEN_FDS", Some("many"))]);
+        let sockets = activated_sockets();
+        set_env(&[("LISTEN_PID", None), ("LISTEN_FDS", None)]);
+        assert_eq!(sockets.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+}